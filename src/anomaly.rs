@@ -0,0 +1,484 @@
+//! Structured findings produced while checking a crate download.
+
+use crate::fetch::ErrorClass;
+use crate::index::{IndexError, Version};
+
+use serde::{Deserialize, Serialize};
+
+use std::fmt;
+
+/// A single anomaly detected for a version of a crate.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Anomaly {
+    /// An expected header was not present in the response.
+    MissingHeader {
+        /// The version whose crate file was requested.
+        version: Version,
+        /// The name of the missing header, in lower case.
+        header: String,
+    },
+    /// The response contained a header that is not in the set of expected headers.
+    UnexpectedHeader {
+        /// The version whose crate file was requested.
+        version: Version,
+        /// The name of the unexpected header, in lower case.
+        header: String,
+    },
+    /// A header was present, but its value differs from the expected one.
+    UnexpectedValue {
+        /// The version whose crate file was requested.
+        version: Version,
+        /// The name of the header, in lower case.
+        header: String,
+        /// The expected value, or the pattern the value should match.
+        expected: String,
+        /// The value in the response.
+        actual: String,
+    },
+    /// The request for the crate file failed altogether, even after retrying.
+    RequestFailed {
+        /// The version whose crate file was requested.
+        version: Version,
+        /// The error message of the last attempt.
+        error: String,
+        /// The class of the error of the last attempt.
+        class: ErrorClass,
+        /// The number of requests made.
+        attempts: u32,
+    },
+    /// The SHA-256 checksum of the downloaded crate file differs from the one
+    /// in the index or lock file.
+    ChecksumMismatch {
+        /// The version whose crate file was downloaded.
+        version: Version,
+        /// The checksum in the index or lock file, hex-encoded.
+        expected: String,
+        /// The checksum of the downloaded file, hex-encoded.
+        actual: String,
+    },
+    /// The MD5 digest of the downloaded crate file differs from the one in
+    /// the `etag` header.
+    ETagMismatch {
+        /// The version whose crate file was downloaded.
+        version: Version,
+        /// The digest in the `etag` header, hex-encoded.
+        expected: String,
+        /// The digest of the downloaded file, hex-encoded.
+        actual: String,
+    },
+    /// The connection failed before the whole body was received.
+    TruncatedBody {
+        /// The version whose crate file was downloaded.
+        version: Version,
+        /// The value of the `content-length` header, if present.
+        content_length: Option<u64>,
+        /// The number of bytes received before the error.
+        received: u64,
+        /// The error that ended the transfer.
+        error: String,
+    },
+    /// The body was received completely, but its length differs from the
+    /// `content-length` header.
+    ContentLengthMismatch {
+        /// The version whose crate file was downloaded.
+        version: Version,
+        /// The value of the `content-length` header.
+        content_length: u64,
+        /// The number of bytes received.
+        received: u64,
+    },
+    /// An issue reported by a custom header check.
+    Custom {
+        /// The version whose crate file was requested.
+        version: Version,
+        /// A short, stable identifier for the kind of issue.
+        kind: String,
+        /// The header the issue refers to, if any.
+        header: Option<String>,
+        /// A description of the issue.
+        message: String,
+    },
+    /// An entry in the index could not be read or parsed.
+    MalformedIndexEntry(IndexError),
+    /// An issue with the response for the sparse index file of a crate.
+    IndexFile {
+        /// The name of the crate.
+        name: String,
+        /// The URL of the index file.
+        url: String,
+        /// The issue found in the response.
+        issue: Issue,
+    },
+}
+
+/// An issue with a response, independent of what was requested.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Issue {
+    /// An expected header, given by name, was not present in the response.
+    MissingHeader(String),
+    /// The response contained a header, given by name, that is not in the set
+    /// of expected headers.
+    UnexpectedHeader(String),
+    /// A header was present, but its value differs from the expected one.
+    UnexpectedValue {
+        /// The name of the header, in lower case.
+        header: String,
+        /// The expected value, or the pattern the value should match.
+        expected: String,
+        /// The value in the response.
+        actual: String,
+    },
+    /// The request failed altogether, even after retrying.
+    RequestFailed {
+        /// The error message of the last attempt.
+        error: String,
+        /// The class of the error of the last attempt.
+        class: ErrorClass,
+        /// The number of requests made.
+        attempts: u32,
+    },
+    /// An issue reported by a custom header check.
+    Custom {
+        /// A short, stable identifier for the kind of issue.
+        kind: String,
+        /// The header the issue refers to, if any.
+        header: Option<String>,
+        /// A description of the issue.
+        message: String,
+    },
+}
+
+impl Issue {
+    /// The anomaly for finding this issue in the response for a crate file.
+    pub fn for_version(self, version: &Version) -> Anomaly {
+        let version = version.clone();
+        match self {
+            Issue::MissingHeader(header) => Anomaly::MissingHeader { version, header },
+            Issue::UnexpectedHeader(header) => Anomaly::UnexpectedHeader { version, header },
+            Issue::UnexpectedValue {
+                header,
+                expected,
+                actual,
+            } => Anomaly::UnexpectedValue {
+                version,
+                header,
+                expected,
+                actual,
+            },
+            Issue::RequestFailed {
+                error,
+                class,
+                attempts,
+            } => Anomaly::RequestFailed {
+                version,
+                error,
+                class,
+                attempts,
+            },
+            Issue::Custom {
+                kind,
+                header,
+                message,
+            } => Anomaly::Custom {
+                version,
+                kind,
+                header,
+                message,
+            },
+        }
+    }
+}
+
+impl Anomaly {
+    /// The version of the crate this anomaly was found for, if known.
+    pub fn version(&self) -> Option<&Version> {
+        match self {
+            Anomaly::MissingHeader { version, .. }
+            | Anomaly::UnexpectedHeader { version, .. }
+            | Anomaly::UnexpectedValue { version, .. }
+            | Anomaly::RequestFailed { version, .. }
+            | Anomaly::ChecksumMismatch { version, .. }
+            | Anomaly::ETagMismatch { version, .. }
+            | Anomaly::TruncatedBody { version, .. }
+            | Anomaly::ContentLengthMismatch { version, .. }
+            | Anomaly::Custom { version, .. } => Some(version),
+            Anomaly::MalformedIndexEntry(_) | Anomaly::IndexFile { .. } => None,
+        }
+    }
+
+    /// The name of the crate this anomaly was found for, if known.
+    pub fn crate_name(&self) -> Option<&str> {
+        match self {
+            Anomaly::IndexFile { name, .. } => Some(name),
+            _ => self.version().map(|version| version.name.as_str()),
+        }
+    }
+
+    /// A short, stable identifier for the kind of anomaly.
+    pub fn kind(&self) -> &str {
+        match self {
+            Anomaly::MissingHeader { .. } => "missing_header",
+            Anomaly::UnexpectedHeader { .. } => "unexpected_header",
+            Anomaly::UnexpectedValue { .. } => "unexpected_value",
+            Anomaly::RequestFailed { .. } => "request_failed",
+            Anomaly::ChecksumMismatch { .. } => "checksum_mismatch",
+            Anomaly::ETagMismatch { .. } => "etag_mismatch",
+            Anomaly::TruncatedBody { .. } => "truncated_body",
+            Anomaly::ContentLengthMismatch { .. } => "content_length_mismatch",
+            Anomaly::Custom { kind, .. } => kind,
+            Anomaly::MalformedIndexEntry(_) => "malformed_index_entry",
+            Anomaly::IndexFile { issue, .. } => match issue {
+                Issue::MissingHeader(_) => "index_missing_header",
+                Issue::UnexpectedHeader(_) => "index_unexpected_header",
+                Issue::UnexpectedValue { .. } => "index_unexpected_value",
+                Issue::RequestFailed { .. } => "index_request_failed",
+                Issue::Custom { kind, .. } => kind,
+            },
+        }
+    }
+
+    /// The name of the header this anomaly refers to, if any.
+    pub fn header(&self) -> Option<&str> {
+        match self {
+            Anomaly::MissingHeader { header, .. }
+            | Anomaly::UnexpectedHeader { header, .. }
+            | Anomaly::UnexpectedValue { header, .. } => Some(header),
+            Anomaly::ContentLengthMismatch { .. } => Some("content-length"),
+            Anomaly::ETagMismatch { .. } => Some("etag"),
+            Anomaly::Custom { header, .. } => header.as_deref(),
+            Anomaly::IndexFile { issue, .. } => match issue {
+                Issue::MissingHeader(header)
+                | Issue::UnexpectedHeader(header)
+                | Issue::UnexpectedValue { header, .. } => Some(header),
+                Issue::RequestFailed { .. } => None,
+                Issue::Custom { header, .. } => header.as_deref(),
+            },
+            _ => None,
+        }
+    }
+
+    /// The class of the error, if the request failed.
+    pub fn error_class(&self) -> Option<ErrorClass> {
+        match self {
+            Anomaly::RequestFailed { class, .. }
+            | Anomaly::IndexFile {
+                issue: Issue::RequestFailed { class, .. },
+                ..
+            } => Some(*class),
+            _ => None,
+        }
+    }
+
+    /// The expected value, if the anomaly is about a value.
+    ///
+    /// For body length anomalies, this is the value of the `content-length` header.
+    pub fn expected(&self) -> Option<String> {
+        match self {
+            Anomaly::UnexpectedValue { expected, .. }
+            | Anomaly::ChecksumMismatch { expected, .. }
+            | Anomaly::ETagMismatch { expected, .. }
+            | Anomaly::IndexFile {
+                issue: Issue::UnexpectedValue { expected, .. },
+                ..
+            } => Some(expected.clone()),
+            Anomaly::TruncatedBody { content_length, .. } => content_length.map(|n| n.to_string()),
+            Anomaly::ContentLengthMismatch { content_length, .. } => {
+                Some(content_length.to_string())
+            }
+            _ => None,
+        }
+    }
+
+    /// The actual value or error message, if any.
+    ///
+    /// For body length anomalies, this is the number of bytes received.
+    pub fn actual(&self) -> Option<String> {
+        match self {
+            Anomaly::UnexpectedValue { actual, .. }
+            | Anomaly::ChecksumMismatch { actual, .. }
+            | Anomaly::ETagMismatch { actual, .. }
+            | Anomaly::IndexFile {
+                issue: Issue::UnexpectedValue { actual, .. },
+                ..
+            } => Some(actual.clone()),
+            Anomaly::RequestFailed { error, .. }
+            | Anomaly::IndexFile {
+                issue: Issue::RequestFailed { error, .. },
+                ..
+            } => Some(error.clone()),
+            Anomaly::Custom { message, .. }
+            | Anomaly::IndexFile {
+                issue: Issue::Custom { message, .. },
+                ..
+            } => Some(message.clone()),
+            Anomaly::TruncatedBody { received, .. }
+            | Anomaly::ContentLengthMismatch { received, .. } => Some(received.to_string()),
+            Anomaly::MalformedIndexEntry(e) => Some(e.error.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// The details of an anomaly, without the version it was found for.
+///
+/// This is the form anomalies are persisted in, e.g. in checkpoints.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub struct Finding {
+    /// The kind of the anomaly, as returned by [`Anomaly::kind`].
+    pub kind: String,
+    /// The header the anomaly refers to, if any.
+    pub header: Option<String>,
+    /// The expected value, if the anomaly is about a value.
+    pub expected: Option<String>,
+    /// The actual value or error message, if any.
+    pub actual: Option<String>,
+}
+
+impl From<&Anomaly> for Finding {
+    fn from(anomaly: &Anomaly) -> Self {
+        Finding {
+            kind: anomaly.kind().to_owned(),
+            header: anomaly.header().map(str::to_owned),
+            expected: anomaly.expected(),
+            actual: anomaly.actual(),
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.kind)?;
+        if let Some(header) = &self.header {
+            write!(f, " '{}'", header)?;
+        }
+        match (&self.expected, &self.actual) {
+            (Some(expected), Some(actual)) => {
+                write!(f, " (expected '{}', got '{}')", expected, actual)
+            }
+            (None, Some(actual)) => write!(f, " ('{}')", actual),
+            (Some(expected), None) => write!(f, " (expected '{}')", expected),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Anomaly::MissingHeader { version, header } => {
+                write!(
+                    f,
+                    "{}: Response did not contain '{}' header.",
+                    version, header
+                )
+            }
+            Anomaly::UnexpectedHeader { version, header } => {
+                write!(
+                    f,
+                    "{}: Response contained unexpected '{}' header.",
+                    version, header
+                )
+            }
+            Anomaly::UnexpectedValue {
+                version,
+                header,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{}: Header '{}' has unexpected value '{}' (expected '{}').",
+                version, header, actual, expected
+            ),
+            Anomaly::RequestFailed {
+                version,
+                error,
+                class,
+                attempts,
+            } => write!(
+                f,
+                "{}: {} ({} error, {} attempt{})",
+                version,
+                error,
+                class,
+                attempts,
+                if *attempts == 1 { "" } else { "s" }
+            ),
+            Anomaly::ChecksumMismatch {
+                version,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{}: Crate file has checksum {}, expected {}.",
+                version, actual, expected
+            ),
+            Anomaly::ETagMismatch {
+                version,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{}: Crate file has MD5 digest {}, but 'etag' is {}.",
+                version, actual, expected
+            ),
+            Anomaly::TruncatedBody {
+                version,
+                content_length,
+                received,
+                error,
+            } => {
+                write!(f, "{}: Body truncated after {} ", version, received)?;
+                if let Some(content_length) = content_length {
+                    write!(f, "of {} ", content_length)?;
+                }
+                write!(f, "bytes: {}", error)
+            }
+            Anomaly::ContentLengthMismatch {
+                version,
+                content_length,
+                received,
+            } => write!(
+                f,
+                "{}: Received {} bytes, but 'content-length' is {}.",
+                version, received, content_length
+            ),
+            Anomaly::Custom {
+                version, message, ..
+            } => write!(f, "{}: {}", version, message),
+            Anomaly::MalformedIndexEntry(e) => write!(f, "Skipped malformed index entry: {}", e),
+            Anomaly::IndexFile { name, issue, .. } => {
+                write!(f, "{} (index file): ", name)?;
+                match issue {
+                    Issue::MissingHeader(header) => {
+                        write!(f, "Response did not contain '{}' header.", header)
+                    }
+                    Issue::UnexpectedHeader(header) => {
+                        write!(f, "Response contained unexpected '{}' header.", header)
+                    }
+                    Issue::UnexpectedValue {
+                        header,
+                        expected,
+                        actual,
+                    } => write!(
+                        f,
+                        "Header '{}' has unexpected value '{}' (expected '{}').",
+                        header, actual, expected
+                    ),
+                    Issue::RequestFailed {
+                        error,
+                        class,
+                        attempts,
+                    } => write!(
+                        f,
+                        "{} ({} error, {} attempt{})",
+                        error,
+                        class,
+                        attempts,
+                        if *attempts == 1 { "" } else { "s" }
+                    ),
+                    Issue::Custom { message, .. } => f.write_str(message),
+                }
+            }
+        }
+    }
+}
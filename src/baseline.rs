@@ -0,0 +1,346 @@
+//! Accepting known anomalies, so only new ones are reported.
+//!
+//! A baseline file lists accepted anomalies as `[[accept]]` tables:
+//!
+//! ```toml
+//! [[accept]]
+//! crate = "legacy-*"          # glob pattern for the crate name
+//! version = "<0.3"            # version, semver requirement or glob (optional)
+//! header = "x-amz-version-id" # (optional)
+//! kind = "missing_header"     # (optional)
+//! reason = "Uploaded before versioning was enabled"
+//! expires = "2025-06-30"      # (optional)
+//! ```
+
+use crate::anomaly::Anomaly;
+
+use chrono::{NaiveDate, Utc};
+use glob::Pattern;
+use semver::VersionReq;
+use serde::Deserialize;
+
+use std::fmt;
+use std::path::Path;
+
+/// The on-disk representation of a baseline file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BaselineFile {
+    #[serde(default)]
+    accept: Vec<EntryFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EntryFile {
+    #[serde(rename = "crate")]
+    name: String,
+    version: Option<String>,
+    header: Option<String>,
+    kind: Option<String>,
+    reason: Option<String>,
+    expires: Option<String>,
+}
+
+/// A list of accepted anomalies.
+///
+/// Entries whose expiry date is before the day the baseline was loaded no
+/// longer accept anything.
+#[derive(Default)]
+pub struct Baseline {
+    entries: Vec<Entry>,
+    today: NaiveDate,
+}
+
+/// A single accepted anomaly, or group of anomalies.
+pub struct Entry {
+    name: Pattern,
+    version: Option<VersionPattern>,
+    header: Option<String>,
+    kind: Option<String>,
+    /// Why the anomalies are accepted.
+    pub reason: Option<String>,
+    /// The last day the entry applies.
+    pub expires: Option<NaiveDate>,
+}
+
+/// The versions an entry applies to.
+enum VersionPattern {
+    /// A single version, e.g. `1.0.0`.
+    Exact(String),
+    /// A semver requirement, e.g. `>=0.1, <0.3`.
+    Range(VersionReq),
+    /// A glob pattern, e.g. `1.*`, matching prereleases and versions that
+    /// aren't valid semver as well.
+    Glob(Pattern),
+}
+
+impl VersionPattern {
+    /// Parse a pattern.  Patterns containing glob metacharacters are always
+    /// globs, even if they are valid semver requirements like `*` or `1.*`,
+    /// since those don't match prereleases.
+    fn parse(s: &str) -> Result<Self, String> {
+        let glob = || {
+            Pattern::new(s)
+                .map(VersionPattern::Glob)
+                .map_err(|e| format!("invalid version pattern '{}': {}", s, e))
+        };
+        if s.contains(['*', '?', '[']) {
+            glob()
+        } else if semver::Version::parse(s).is_ok() {
+            Ok(VersionPattern::Exact(s.to_owned()))
+        } else if let Ok(req) = VersionReq::parse(s) {
+            Ok(VersionPattern::Range(req))
+        } else {
+            glob()
+        }
+    }
+
+    fn matches(&self, vers: &str) -> bool {
+        match self {
+            VersionPattern::Exact(expected) => vers == expected,
+            VersionPattern::Range(req) => {
+                semver::Version::parse(vers).is_ok_and(|version| req.matches(&version))
+            }
+            VersionPattern::Glob(pattern) => pattern.matches(vers),
+        }
+    }
+}
+
+impl Baseline {
+    /// Load the baseline from a TOML file, or from a JSON file if the file
+    /// name ends in `.json`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read baseline file {}: {}", path.display(), e))?;
+        let file = if path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::from_str(&contents).map_err(|e| e.to_string())
+        } else {
+            toml::from_str(&contents).map_err(|e| e.to_string())
+        };
+        file.and_then(Self::from_file)
+            .map_err(|e| format!("invalid baseline file {}: {}", path.display(), e))
+    }
+
+    fn from_file(file: BaselineFile) -> Result<Self, String> {
+        let entries = file
+            .accept
+            .into_iter()
+            .map(|entry| {
+                Ok(Entry {
+                    name: Pattern::new(&entry.name)
+                        .map_err(|e| format!("invalid crate pattern '{}': {}", entry.name, e))?,
+                    version: entry
+                        .version
+                        .as_deref()
+                        .map(VersionPattern::parse)
+                        .transpose()?,
+                    header: entry.header.map(|h| h.to_lowercase()),
+                    kind: entry.kind,
+                    reason: entry.reason,
+                    expires: entry
+                        .expires
+                        .map(|date| {
+                            NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+                                .map_err(|e| format!("invalid expiry date '{}': {}", date, e))
+                        })
+                        .transpose()?,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Baseline {
+            entries,
+            today: Utc::now().date_naive(),
+        })
+    }
+
+    /// The entries that have expired.
+    pub fn expired(&self) -> impl Iterator<Item = &Entry> {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.is_expired(self.today))
+    }
+
+    /// Whether the anomaly is accepted by an entry that hasn't expired.
+    ///
+    /// Anomalies that don't belong to a crate are never accepted, and
+    /// anomalies that don't belong to a version are only accepted by entries
+    /// without a version pattern.
+    pub fn accepts(&self, anomaly: &Anomaly) -> bool {
+        let name = match anomaly.crate_name() {
+            Some(name) => name,
+            None => return false,
+        };
+        let vers = anomaly.version().map(|version| version.vers.as_str());
+        self.entries.iter().any(|entry| {
+            !entry.is_expired(self.today)
+                && entry.name.matches(name)
+                && entry
+                    .version
+                    .as_ref()
+                    .is_none_or(|pattern| vers.is_some_and(|vers| pattern.matches(vers)))
+                && entry
+                    .header
+                    .as_ref()
+                    .is_none_or(|header| Some(header.as_str()) == anomaly.header())
+                && entry
+                    .kind
+                    .as_ref()
+                    .is_none_or(|kind| kind == anomaly.kind())
+        })
+    }
+}
+
+impl Entry {
+    fn is_expired(&self, today: NaiveDate) -> bool {
+        self.expires.is_some_and(|expires| expires < today)
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "crate '{}'", self.name)?;
+        match &self.version {
+            Some(VersionPattern::Exact(version)) => write!(f, ", version '{}'", version)?,
+            Some(VersionPattern::Range(req)) => write!(f, ", version '{}'", req)?,
+            Some(VersionPattern::Glob(pattern)) => write!(f, ", version '{}'", pattern)?,
+            None => {}
+        }
+        if let Some(header) = &self.header {
+            write!(f, ", header '{}'", header)?;
+        }
+        if let Some(kind) = &self.kind {
+            write!(f, ", kind '{}'", kind)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anomaly::Issue;
+    use crate::index::Version;
+
+    fn baseline(toml: &str) -> Baseline {
+        let mut baseline = Baseline::from_file(toml::from_str(toml).unwrap()).unwrap();
+        baseline.today = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
+        baseline
+    }
+
+    fn missing(name: &str, vers: &str, header: &str) -> Anomaly {
+        Anomaly::MissingHeader {
+            version: Version {
+                name: name.to_owned(),
+                vers: vers.to_owned(),
+                ..Version::default()
+            },
+            header: header.to_owned(),
+        }
+    }
+
+    #[test]
+    fn version_patterns_are_parsed_in_order() {
+        assert!(matches!(
+            VersionPattern::parse("1.0.0"),
+            Ok(VersionPattern::Exact(_))
+        ));
+        assert!(matches!(
+            VersionPattern::parse(">=0.1, <0.3"),
+            Ok(VersionPattern::Range(_))
+        ));
+        assert!(matches!(
+            VersionPattern::parse("1.0.0-*.x"),
+            Ok(VersionPattern::Glob(_))
+        ));
+        assert!(matches!(
+            VersionPattern::parse("*"),
+            Ok(VersionPattern::Glob(_))
+        ));
+        assert!(matches!(
+            VersionPattern::parse("1.*"),
+            Ok(VersionPattern::Glob(_))
+        ));
+        assert!(VersionPattern::parse("[").is_err());
+    }
+
+    #[test]
+    fn version_patterns_match() {
+        let exact = VersionPattern::parse("1.0.0").unwrap();
+        assert!(exact.matches("1.0.0"));
+        assert!(!exact.matches("1.0.1"));
+        let range = VersionPattern::parse("<0.3").unwrap();
+        assert!(range.matches("0.2.9"));
+        assert!(!range.matches("0.3.0"));
+        assert!(!range.matches("not-semver"));
+        let glob = VersionPattern::parse("0.1.0-*.x").unwrap();
+        assert!(glob.matches("0.1.0-beta.x"));
+    }
+
+    #[test]
+    fn glob_patterns_match_prereleases() {
+        let any = VersionPattern::parse("*").unwrap();
+        assert!(any.matches("1.0.0"));
+        assert!(any.matches("1.0.0-beta.1"));
+        assert!(any.matches("not-semver"));
+        let major = VersionPattern::parse("1.*").unwrap();
+        assert!(major.matches("1.2.0"));
+        assert!(major.matches("1.2.0-rc.1"));
+        assert!(!major.matches("2.0.0"));
+    }
+
+    #[test]
+    fn all_given_fields_must_match() {
+        let baseline = baseline(
+            r#"
+            [[accept]]
+            crate = "legacy-*"
+            version = "<0.3"
+            header = "X-Amz-Version-Id"
+            kind = "missing_header"
+            "#,
+        );
+        assert!(baseline.accepts(&missing("legacy-a", "0.2.0", "x-amz-version-id")));
+        assert!(!baseline.accepts(&missing("modern", "0.2.0", "x-amz-version-id")));
+        assert!(!baseline.accepts(&missing("legacy-a", "0.3.0", "x-amz-version-id")));
+        assert!(!baseline.accepts(&missing("legacy-a", "0.2.0", "etag")));
+        assert!(!baseline.accepts(&Anomaly::UnexpectedHeader {
+            version: Version {
+                name: "legacy-a".to_owned(),
+                vers: "0.2.0".to_owned(),
+                ..Version::default()
+            },
+            header: "x-amz-version-id".to_owned(),
+        }));
+    }
+
+    #[test]
+    fn expired_entries_accept_nothing() {
+        let baseline = baseline(
+            r#"
+            [[accept]]
+            crate = "old"
+            expires = "2024-12-31"
+
+            [[accept]]
+            crate = "current"
+            expires = "2025-01-01"
+            "#,
+        );
+        assert!(!baseline.accepts(&missing("old", "1.0.0", "etag")));
+        assert!(baseline.accepts(&missing("current", "1.0.0", "etag")));
+        assert_eq!(baseline.expired().count(), 1);
+    }
+
+    #[test]
+    fn anomalies_without_version_need_an_entry_without_version() {
+        let index_file = Anomaly::IndexFile {
+            name: "serde".to_owned(),
+            url: "https://index.crates.io/se/rd/serde".to_owned(),
+            issue: Issue::MissingHeader("etag".to_owned()),
+        };
+        assert!(baseline("[[accept]]\ncrate = \"serde\"").accepts(&index_file));
+        assert!(!baseline("[[accept]]\ncrate = \"serde\"\nversion = \"*\"").accepts(&index_file));
+    }
+}
@@ -0,0 +1,191 @@
+//! Pluggable checks of response headers.
+//!
+//! A [`HeaderCheck`] looks at a single response and returns the issues it
+//! found.  The [`Checks`] registry runs a list of checks; it is built from the
+//! [`Rules`](crate::Rules) loaded from a rules file, and library users can add
+//! their own checks to it.
+
+use crate::anomaly::{Anomaly, Issue};
+use crate::index::Version;
+
+use regex::Regex;
+use reqwest::header::HeaderMap;
+
+use std::collections::BTreeSet;
+
+/// A response to be checked.
+#[derive(Clone, Copy, Debug)]
+pub struct CheckedResponse<'a> {
+    /// The version whose crate file was requested, or `None` for other files,
+    /// like sparse index files.
+    pub version: Option<&'a Version>,
+    /// The URL of the request.
+    pub url: &'a str,
+    /// The HTTP status code.
+    pub status: u16,
+    /// The response headers.
+    pub headers: &'a HeaderMap,
+}
+
+/// A check of the headers of a response.
+pub trait HeaderCheck: Send + Sync {
+    /// Check the response and return the issues found, if any.
+    fn check(&self, response: &CheckedResponse<'_>) -> Vec<Issue>;
+}
+
+/// A list of checks that are run on every response.
+#[derive(Default)]
+pub struct Checks {
+    checks: Vec<Box<dyn HeaderCheck>>,
+}
+
+impl Checks {
+    /// Create an empty list of checks.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a check to the list.
+    pub fn add<C: HeaderCheck + 'static>(&mut self, check: C) -> &mut Self {
+        self.checks.push(Box::new(check));
+        self
+    }
+
+    /// Run all checks, in the order they were added.
+    pub fn check(&self, response: &CheckedResponse<'_>) -> Vec<Issue> {
+        self.checks
+            .iter()
+            .flat_map(|check| check.check(response))
+            .collect()
+    }
+
+    /// Run all checks on the response for the crate file of `version`.
+    pub fn check_version(
+        &self,
+        version: &Version,
+        url: &str,
+        status: u16,
+        headers: &HeaderMap,
+    ) -> Vec<Anomaly> {
+        let response = CheckedResponse {
+            version: Some(version),
+            url,
+            status,
+            headers,
+        };
+        self.check(&response)
+            .into_iter()
+            .map(|issue| issue.for_version(version))
+            .collect()
+    }
+}
+
+/// The names of the headers in a response, in lower case.
+fn header_names(headers: &HeaderMap) -> BTreeSet<String> {
+    headers
+        .keys()
+        .map(|key| key.as_str().to_lowercase())
+        .collect()
+}
+
+/// Reports headers that are missing from the response.
+pub struct RequiredHeaders {
+    required: BTreeSet<String>,
+}
+
+impl RequiredHeaders {
+    /// Require the given headers.
+    pub fn new<I: IntoIterator<Item = S>, S: AsRef<str>>(headers: I) -> Self {
+        RequiredHeaders {
+            required: headers
+                .into_iter()
+                .map(|h| h.as_ref().to_lowercase())
+                .collect(),
+        }
+    }
+}
+
+impl HeaderCheck for RequiredHeaders {
+    fn check(&self, response: &CheckedResponse<'_>) -> Vec<Issue> {
+        self.required
+            .difference(&header_names(response.headers))
+            .map(|header| Issue::MissingHeader(header.clone()))
+            .collect()
+    }
+}
+
+/// Reports headers that are not in the set of expected headers.
+pub struct KnownHeaders {
+    known: BTreeSet<String>,
+}
+
+impl KnownHeaders {
+    /// Expect only the given headers.
+    pub fn new<I: IntoIterator<Item = S>, S: AsRef<str>>(headers: I) -> Self {
+        KnownHeaders {
+            known: headers
+                .into_iter()
+                .map(|h| h.as_ref().to_lowercase())
+                .collect(),
+        }
+    }
+}
+
+impl HeaderCheck for KnownHeaders {
+    fn check(&self, response: &CheckedResponse<'_>) -> Vec<Issue> {
+        header_names(response.headers)
+            .difference(&self.known)
+            .map(|header| Issue::UnexpectedHeader(header.clone()))
+            .collect()
+    }
+}
+
+/// What the value of a header is expected to be.
+#[derive(Clone, Debug)]
+pub enum Expectation {
+    /// The value must be exactly the given string.
+    Equals(String),
+    /// The value must match the regular expression.
+    Matches(Regex),
+}
+
+/// Reports a header whose value differs from the expected one.
+///
+/// A missing header is not reported; use [`RequiredHeaders`] for that.
+pub struct ExpectedValue {
+    header: String,
+    expectation: Expectation,
+}
+
+impl ExpectedValue {
+    /// Expect the value of `header` to meet the expectation.
+    pub fn new(header: &str, expectation: Expectation) -> Self {
+        ExpectedValue {
+            header: header.to_lowercase(),
+            expectation,
+        }
+    }
+}
+
+impl HeaderCheck for ExpectedValue {
+    fn check(&self, response: &CheckedResponse<'_>) -> Vec<Issue> {
+        let actual = match response
+            .headers
+            .get(&self.header)
+            .and_then(|v| v.to_str().ok())
+        {
+            Some(actual) => actual,
+            None => return vec![],
+        };
+        let expected = match &self.expectation {
+            Expectation::Equals(value) if actual != value => value.as_str(),
+            Expectation::Matches(regex) if !regex.is_match(actual) => regex.as_str(),
+            _ => return vec![],
+        };
+        vec![Issue::UnexpectedValue {
+            header: self.header.clone(),
+            expected: expected.to_owned(),
+            actual: actual.to_owned(),
+        }]
+    }
+}
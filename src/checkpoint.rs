@@ -0,0 +1,103 @@
+//! Persisting the progress of a scan, so it can be resumed after an interruption.
+
+use crate::anomaly::{Anomaly, Finding};
+use crate::fetch::Outcome;
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// A single line of the checkpoint file, recording a completed version.
+#[derive(Deserialize, Serialize)]
+struct Entry {
+    #[serde(rename = "crate")]
+    name: String,
+    version: String,
+    findings: Vec<Finding>,
+}
+
+/// An append-only log of the versions a scan has completed.
+///
+/// The checkpoint file contains one JSON object per line, with the crate name,
+/// the version and the findings for that version.
+pub struct Checkpoint {
+    file: File,
+}
+
+impl Checkpoint {
+    /// Open the checkpoint file at `path`.
+    ///
+    /// When resuming, the names and versions of the crates completed in
+    /// earlier runs are returned, and new entries are appended to the file.
+    /// Otherwise, the file is truncated.
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        resume: bool,
+    ) -> Result<(Self, HashSet<(String, String)>), String> {
+        let path = path.as_ref();
+        let error = |e: std::io::Error| format!("cannot open checkpoint {}: {}", path.display(), e);
+        let completed = if resume && path.exists() {
+            let reader = BufReader::new(File::open(path).map_err(error)?);
+            let mut completed = HashSet::new();
+            for line in reader.lines() {
+                // A line that cannot be parsed was most likely cut short when
+                // the previous run was killed, so the version is checked again.
+                if let Ok(entry) = serde_json::from_str::<Entry>(&line.map_err(error)?) {
+                    completed.insert((entry.name, entry.version));
+                }
+            }
+            completed
+        } else {
+            HashSet::new()
+        };
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .write(true)
+            .append(resume)
+            .truncate(!resume)
+            .open(path)
+            .map_err(error)?;
+        if resume && !ends_with_newline(&mut file).map_err(error)? {
+            file.write_all(b"\n").map_err(error)?;
+        }
+        Ok((Checkpoint { file }, completed))
+    }
+
+    /// Record the outcome of checking a version.
+    ///
+    /// Versions whose request failed are not recorded, so they are retried
+    /// when resuming.
+    pub fn record(&mut self, outcome: &Outcome) -> std::io::Result<()> {
+        if outcome
+            .anomalies
+            .iter()
+            .any(|anomaly| matches!(anomaly, Anomaly::RequestFailed { .. }))
+        {
+            return Ok(());
+        }
+        let entry = Entry {
+            name: outcome.version.name.clone(),
+            version: outcome.version.vers.clone(),
+            findings: outcome.anomalies.iter().map(Finding::from).collect(),
+        };
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes())
+    }
+}
+
+/// Whether the file is empty or ends with a newline, i.e. whether new lines can
+/// be appended as they are.
+fn ends_with_newline(file: &mut File) -> std::io::Result<bool> {
+    if file.metadata()?.len() == 0 {
+        return Ok(true);
+    }
+    let mut last = [0];
+    file.seek(SeekFrom::End(-1))?;
+    file.read_exact(&mut last)?;
+    Ok(last[0] == b'\n')
+}
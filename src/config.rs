@@ -0,0 +1,162 @@
+//! The registry configuration stored in `config.json` at the root of the index.
+
+use crate::index::Version;
+
+use serde::Deserialize;
+
+use std::path::Path;
+
+/// The markers Cargo substitutes in the `dl` template.
+const MARKERS: &[&str] = &[
+    "{crate}",
+    "{version}",
+    "{prefix}",
+    "{lowerprefix}",
+    "{sha256-checksum}",
+];
+
+/// The contents of the `config.json` file of a registry index.
+#[derive(Clone, Debug, Deserialize)]
+pub struct RegistryConfig {
+    /// The download URL or URL template for crate files.
+    pub dl: String,
+}
+
+impl RegistryConfig {
+    /// Read the configuration from the `config.json` file in the index root.
+    pub fn load<P: AsRef<Path>>(index_root: P) -> Result<Self, String> {
+        let path = index_root.as_ref().join("config.json");
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        Self::parse(&contents, &path.display().to_string())
+    }
+
+    /// Parse the contents of a `config.json` file read from `origin`.
+    pub fn parse(contents: &str, origin: &str) -> Result<Self, String> {
+        serde_json::from_str(contents).map_err(|e| format!("invalid {}: {}", origin, e))
+    }
+}
+
+/// The template for download URLs of crate files.
+#[derive(Clone, Debug)]
+pub struct DownloadTemplate {
+    template: String,
+}
+
+impl DownloadTemplate {
+    /// Create a template from the `dl` field of a registry configuration.
+    ///
+    /// Like Cargo, `/{crate}/{version}/download` is appended if the value
+    /// does not contain any markers.
+    pub fn new(dl: &str) -> Self {
+        let template = if MARKERS.iter().any(|marker| dl.contains(marker)) {
+            dl.to_owned()
+        } else {
+            format!(
+                "{}/{{crate}}/{{version}}/download",
+                dl.trim_end_matches('/')
+            )
+        };
+        DownloadTemplate { template }
+    }
+
+    /// The download URL for the given version of a crate.
+    pub fn url(&self, version: &Version) -> String {
+        let prefix = prefix(&version.name);
+        self.template
+            .replace("{crate}", &version.name)
+            .replace("{version}", &version.vers)
+            .replace("{prefix}", &prefix)
+            .replace("{lowerprefix}", &prefix.to_lowercase())
+            .replace(
+                "{sha256-checksum}",
+                version.cksum.as_deref().unwrap_or_default(),
+            )
+    }
+}
+
+/// The directory prefix of a crate in the index, e.g. `se/rd` for `serde`.
+///
+/// Invalid names, like an empty one, get a prefix that does not exist in the
+/// index instead of causing a panic.
+pub fn prefix(name: &str) -> String {
+    match name.len() {
+        0 => String::new(),
+        1 => "1".to_owned(),
+        2 => "2".to_owned(),
+        3 => format!("3/{}", name.get(..1).unwrap_or_default()),
+        _ => format!(
+            "{}/{}",
+            name.get(..2).unwrap_or_default(),
+            name.get(2..4).unwrap_or_default()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(name: &str) -> Version {
+        Version {
+            name: name.to_owned(),
+            vers: "1.0.0".to_owned(),
+            cksum: Some("abc".to_owned()),
+            ..Version::default()
+        }
+    }
+
+    #[test]
+    fn prefix_depends_on_the_name_length() {
+        assert_eq!(prefix(""), "");
+        assert_eq!(prefix("a"), "1");
+        assert_eq!(prefix("ab"), "2");
+        assert_eq!(prefix("abc"), "3/a");
+        assert_eq!(prefix("abcd"), "ab/cd");
+        assert_eq!(prefix("serde"), "se/rd");
+    }
+
+    #[test]
+    fn prefix_does_not_panic_on_non_ascii_names() {
+        assert_eq!(prefix("äbc"), "ä/bc");
+        assert_eq!(prefix("aäbc"), "/");
+    }
+
+    #[test]
+    fn template_without_markers_gets_the_default_path() {
+        let template = DownloadTemplate::new("https://static.crates.io/crates/");
+        assert_eq!(
+            template.url(&version("serde")),
+            "https://static.crates.io/crates/serde/1.0.0/download"
+        );
+    }
+
+    #[test]
+    fn template_markers_are_replaced() {
+        let template = DownloadTemplate::new(
+            "https://example.com/{prefix}/{lowerprefix}/{crate}-{version}?{sha256-checksum}",
+        );
+        assert_eq!(
+            template.url(&version("a")),
+            "https://example.com/1/1/a-1.0.0?abc"
+        );
+        assert_eq!(
+            template.url(&version("Ab")),
+            "https://example.com/2/2/Ab-1.0.0?abc"
+        );
+        assert_eq!(
+            template.url(&version("Abc")),
+            "https://example.com/3/A/3/a/Abc-1.0.0?abc"
+        );
+        assert_eq!(
+            template.url(&version("SerDe")),
+            "https://example.com/Se/rD/se/rd/SerDe-1.0.0?abc"
+        );
+    }
+
+    #[test]
+    fn template_handles_empty_names() {
+        let template = DownloadTemplate::new("https://example.com/{prefix}/{crate}");
+        assert_eq!(template.url(&version("")), "https://example.com//");
+    }
+}
@@ -0,0 +1,275 @@
+//! Persisting observed responses and findings in an SQLite database.
+
+use crate::anomaly::Finding;
+use crate::diff::FindingSet;
+use crate::fetch::{Observation, Outcome};
+use crate::index::Version;
+
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use rusqlite::{params, Connection, OptionalExtension};
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS runs (
+        id INTEGER PRIMARY KEY,
+        started_at TEXT NOT NULL,
+        source TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS responses (
+        id INTEGER PRIMARY KEY,
+        run_id INTEGER NOT NULL REFERENCES runs (id),
+        crate TEXT NOT NULL,
+        version TEXT NOT NULL,
+        url TEXT NOT NULL,
+        attempts INTEGER NOT NULL,
+        status INTEGER NOT NULL,
+        elapsed_ms INTEGER NOT NULL,
+        fetched_at TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS responses_by_version ON responses (crate, version, run_id);
+    CREATE TABLE IF NOT EXISTS headers (
+        response_id INTEGER NOT NULL REFERENCES responses (id),
+        name TEXT NOT NULL,
+        value BLOB NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS headers_by_response ON headers (response_id);
+    CREATE TABLE IF NOT EXISTS sizes (
+        response_id INTEGER PRIMARY KEY REFERENCES responses (id),
+        size INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS findings (
+        run_id INTEGER NOT NULL REFERENCES runs (id),
+        crate TEXT NOT NULL,
+        version TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        header TEXT,
+        expected TEXT,
+        actual TEXT
+    );
+    CREATE INDEX IF NOT EXISTS findings_by_run ON findings (run_id, crate, version);
+";
+
+/// The number of outcomes written per transaction.
+const BATCH_SIZE: usize = 1000;
+
+/// A database recording the responses and findings of scan runs.
+pub struct Database {
+    conn: Connection,
+    run_id: Option<i64>,
+    pending: usize,
+}
+
+impl Database {
+    /// Open the database at `path`, creating it if necessary.
+    pub fn open<P: AsRef<Path>>(path: P) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL;")?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Database {
+            conn,
+            run_id: None,
+            pending: 0,
+        })
+    }
+
+    /// Start recording a new scan run of the given source, and return its ID.
+    pub fn start_run(&mut self, source: &str) -> rusqlite::Result<i64> {
+        self.conn.execute(
+            "INSERT INTO runs (started_at, source) VALUES (?1, ?2)",
+            params![Utc::now().to_rfc3339(), source],
+        )?;
+        let run_id = self.conn.last_insert_rowid();
+        self.run_id = Some(run_id);
+        self.conn.execute_batch("BEGIN")?;
+        Ok(run_id)
+    }
+
+    /// Record the response and the findings for a version in the current run.
+    pub fn record(&mut self, outcome: &Outcome) -> rusqlite::Result<()> {
+        let run_id = self.run_id.expect("no run started");
+        let version = &outcome.version;
+        if let Some(observation) = &outcome.observation {
+            self.conn.execute(
+                "INSERT INTO responses
+                 (run_id, crate, version, url, attempts, status, elapsed_ms, fetched_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    run_id,
+                    version.name,
+                    version.vers,
+                    outcome.url,
+                    outcome.attempts,
+                    observation.status,
+                    observation.elapsed.as_millis() as i64,
+                    observation.fetched_at.to_rfc3339(),
+                ],
+            )?;
+            let response_id = self.conn.last_insert_rowid();
+            let mut insert = self.conn.prepare_cached(
+                "INSERT INTO headers (response_id, name, value) VALUES (?1, ?2, ?3)",
+            )?;
+            for (name, value) in &observation.headers {
+                insert.execute(params![response_id, name.as_str(), value.as_bytes()])?;
+            }
+            if let Some(size) = outcome.size {
+                self.conn.execute(
+                    "INSERT INTO sizes (response_id, size) VALUES (?1, ?2)",
+                    params![response_id, size as i64],
+                )?;
+            }
+        }
+        let mut insert = self.conn.prepare_cached(
+            "INSERT INTO findings (run_id, crate, version, kind, header, expected, actual)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        )?;
+        for finding in outcome.anomalies.iter().map(Finding::from) {
+            insert.execute(params![
+                run_id,
+                version.name,
+                version.vers,
+                finding.kind,
+                finding.header,
+                finding.expected,
+                finding.actual,
+            ])?;
+        }
+        self.pending += 1;
+        if self.pending >= BATCH_SIZE {
+            self.conn.execute_batch("COMMIT; BEGIN")?;
+            self.pending = 0;
+        }
+        Ok(())
+    }
+
+    /// Commit all outstanding records of the current run.
+    pub fn finish(&mut self) -> rusqlite::Result<()> {
+        if self.run_id.take().is_some() {
+            self.conn.execute_batch("COMMIT")?;
+        }
+        Ok(())
+    }
+
+    /// Whether a run with the given ID exists.
+    pub fn has_run(&self, run_id: i64) -> rusqlite::Result<bool> {
+        let row = self
+            .conn
+            .query_row("SELECT 1 FROM runs WHERE id = ?1", [run_id], |_| Ok(()))
+            .optional()?;
+        Ok(row.is_some())
+    }
+
+    /// Load all responses stored for a run, to check them again without
+    /// contacting the server.
+    ///
+    /// The versions only have their name and version number set.
+    pub fn responses(&self, run_id: i64) -> rusqlite::Result<Vec<StoredResponse>> {
+        let mut headers = self
+            .conn
+            .prepare("SELECT name, value FROM headers WHERE response_id = ?1")?;
+        let mut responses = self.conn.prepare(
+            "SELECT id, crate, version, url, attempts, status, elapsed_ms, fetched_at
+             FROM responses WHERE run_id = ?1 ORDER BY id",
+        )?;
+        let rows = responses.query_map([run_id], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                Version {
+                    name: row.get(1)?,
+                    vers: row.get(2)?,
+                    ..Version::default()
+                },
+                row.get(3)?,
+                row.get(4)?,
+                row.get(5)?,
+                row.get::<_, i64>(6)?,
+                row.get::<_, String>(7)?,
+            ))
+        })?;
+        let mut stored = vec![];
+        for row in rows {
+            let (id, version, url, attempts, status, elapsed_ms, fetched_at) = row?;
+            let mut header_map = HeaderMap::new();
+            let pairs = headers.query_map([id], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?))
+            })?;
+            for pair in pairs {
+                let (name, value) = pair?;
+                if let (Ok(name), Ok(value)) = (
+                    HeaderName::from_bytes(name.as_bytes()),
+                    HeaderValue::from_bytes(&value),
+                ) {
+                    header_map.append(name, value);
+                }
+            }
+            stored.push(StoredResponse {
+                version,
+                url,
+                attempts,
+                observation: Observation {
+                    status,
+                    headers: header_map,
+                    elapsed: Duration::from_millis(elapsed_ms as u64),
+                    fetched_at: DateTime::parse_from_rfc3339(&fetched_at)
+                        .map(|t| t.with_timezone(&Utc))
+                        .unwrap_or_else(|_| Utc::now()),
+                },
+            });
+        }
+        Ok(stored)
+    }
+
+    /// Load the sizes of the crate files downloaded in a run, keyed by crate
+    /// name and version number.
+    pub fn sizes(&self, run_id: i64) -> rusqlite::Result<HashMap<(String, String), u64>> {
+        let mut query = self.conn.prepare(
+            "SELECT responses.crate, responses.version, sizes.size
+             FROM sizes JOIN responses ON responses.id = sizes.response_id
+             WHERE responses.run_id = ?1",
+        )?;
+        let rows = query.query_map([run_id], |row| {
+            Ok(((row.get(0)?, row.get(1)?), row.get::<_, i64>(2)? as u64))
+        })?;
+        rows.collect()
+    }
+
+    /// Load all findings recorded for a run.
+    pub fn findings(&self, run_id: i64) -> rusqlite::Result<FindingSet> {
+        let mut query = self.conn.prepare(
+            "SELECT crate, version, kind, header, expected, actual
+             FROM findings WHERE run_id = ?1",
+        )?;
+        let rows = query.query_map([run_id], |row| {
+            Ok((
+                (row.get(0)?, row.get(1)?),
+                Finding {
+                    kind: row.get(2)?,
+                    header: row.get(3)?,
+                    expected: row.get(4)?,
+                    actual: row.get(5)?,
+                },
+            ))
+        })?;
+        let mut findings = FindingSet::new();
+        for row in rows {
+            let (key, finding) = row?;
+            findings.entry(key).or_default().insert(finding);
+        }
+        Ok(findings)
+    }
+}
+
+/// A response loaded from the database.
+pub struct StoredResponse {
+    /// The version whose crate file was requested.
+    pub version: Version,
+    /// The URL of the crate file.
+    pub url: String,
+    /// The number of requests it took to get the response.
+    pub attempts: u32,
+    /// The response as it was observed.
+    pub observation: Observation,
+}
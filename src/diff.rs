@@ -0,0 +1,222 @@
+//! Comparing the findings of two scan runs.
+
+use crate::anomaly::Finding;
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+/// The findings of a run, grouped by crate name and version.
+pub type FindingSet = BTreeMap<(String, String), BTreeSet<Finding>>;
+
+/// A line of a JSON output or checkpoint file.
+///
+/// JSON output has one finding per line, while checkpoint files have all
+/// findings of a version in a single line.
+#[derive(Deserialize)]
+struct Line {
+    #[serde(rename = "crate")]
+    name: Option<String>,
+    version: Option<String>,
+    findings: Option<Vec<Finding>>,
+    #[serde(flatten)]
+    finding: Option<Finding>,
+}
+
+/// Load the findings from a file written with `--format json` or `--checkpoint`.
+///
+/// Findings that don't belong to a crate version, like malformed index
+/// entries, are ignored.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<FindingSet, String> {
+    let path = path.as_ref();
+    let error = |e: &dyn fmt::Display| format!("cannot read {}: {}", path.display(), e);
+    let file = std::fs::File::open(path).map_err(|e| error(&e))?;
+    let mut findings = FindingSet::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| error(&e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let line: Line = serde_json::from_str(&line)
+            .map_err(|e| format!("{}:{}: {}", path.display(), i + 1, e))?;
+        let (name, version) = match (line.name, line.version) {
+            (Some(name), Some(version)) => (name, version),
+            _ => continue,
+        };
+        let entry = findings.entry((name, version)).or_default();
+        entry.extend(line.findings.into_iter().flatten());
+        entry.extend(line.finding);
+    }
+    Ok(findings)
+}
+
+/// How a finding differs between two runs.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    /// The finding only appears in the new run.
+    New,
+    /// The finding only appears in the old run.
+    Resolved,
+    /// A finding of the same kind for the same header appears in both runs,
+    /// but with different values.
+    Changed,
+}
+
+/// A single difference between two runs.
+#[derive(Debug, Serialize)]
+pub struct Change {
+    /// The name of the crate.
+    #[serde(rename = "crate")]
+    pub name: String,
+    /// The version of the crate.
+    pub version: String,
+    /// How the findings differ.
+    pub change: ChangeKind,
+    /// The finding in the old run, unless it is new.
+    pub old: Option<Finding>,
+    /// The finding in the new run, unless it was resolved.
+    pub new: Option<Finding>,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}): ", self.name, self.version)?;
+        match (&self.old, &self.new) {
+            (None, Some(new)) => write!(f, "new: {}", new),
+            (Some(old), None) => write!(f, "resolved: {}", old),
+            (Some(old), Some(new)) => write!(f, "changed: {} -> {}", old, new),
+            (None, None) => unreachable!(),
+        }
+    }
+}
+
+/// Compare the findings of two runs.
+///
+/// Versions without any findings are not listed in the output of a scan, so a
+/// version that is missing from the new run counts as resolved.
+pub fn diff(old: &FindingSet, new: &FindingSet) -> Vec<Change> {
+    let empty = BTreeSet::new();
+    let keys: BTreeSet<_> = old.keys().chain(new.keys()).collect();
+    let mut changes = vec![];
+    for key in keys {
+        let old_findings = old.get(key).unwrap_or(&empty);
+        let new_findings = new.get(key).unwrap_or(&empty);
+        let mut resolved: Vec<_> = old_findings.difference(new_findings).collect();
+        for finding in new_findings.difference(old_findings) {
+            let same_check = resolved
+                .iter()
+                .position(|old| old.kind == finding.kind && old.header == finding.header);
+            let (change, old) = match same_check {
+                Some(i) => (ChangeKind::Changed, Some(resolved.remove(i).clone())),
+                None => (ChangeKind::New, None),
+            };
+            changes.push(Change {
+                name: key.0.clone(),
+                version: key.1.clone(),
+                change,
+                old,
+                new: Some(finding.clone()),
+            });
+        }
+        for finding in resolved {
+            changes.push(Change {
+                name: key.0.clone(),
+                version: key.1.clone(),
+                change: ChangeKind::Resolved,
+                old: Some(finding.clone()),
+                new: None,
+            });
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finding(kind: &str, header: &str, actual: &str) -> Finding {
+        Finding {
+            kind: kind.to_owned(),
+            header: Some(header.to_owned()),
+            expected: None,
+            actual: Some(actual.to_owned()),
+        }
+    }
+
+    fn set(findings: &[(&str, Finding)]) -> FindingSet {
+        let mut set = FindingSet::new();
+        for (name, finding) in findings {
+            set.entry((name.to_string(), "1.0.0".to_owned()))
+                .or_default()
+                .insert(finding.clone());
+        }
+        set
+    }
+
+    fn summary(changes: &[Change]) -> Vec<(&str, ChangeKind)> {
+        changes
+            .iter()
+            .map(|change| (change.name.as_str(), change.change))
+            .collect()
+    }
+
+    #[test]
+    fn unchanged_findings_are_not_reported() {
+        let findings = set(&[("a", finding("unexpected_value", "server", "x"))]);
+        assert!(diff(&findings, &findings).is_empty());
+    }
+
+    #[test]
+    fn new_and_resolved_findings() {
+        let old = set(&[("a", finding("missing_header", "etag", "x"))]);
+        let new = set(&[("b", finding("missing_header", "etag", "x"))]);
+        let changes = diff(&old, &new);
+        assert_eq!(
+            summary(&changes),
+            [("a", ChangeKind::Resolved), ("b", ChangeKind::New)]
+        );
+        assert_eq!(changes[0].new, None);
+        assert_eq!(changes[1].old, None);
+    }
+
+    #[test]
+    fn same_check_with_another_value_is_changed() {
+        let old = set(&[("a", finding("unexpected_value", "server", "x"))]);
+        let new = set(&[
+            ("a", finding("unexpected_value", "server", "y")),
+            ("a", finding("unexpected_value", "via", "y")),
+        ]);
+        let changes = diff(&old, &new);
+        assert_eq!(
+            summary(&changes),
+            [("a", ChangeKind::Changed), ("a", ChangeKind::New)]
+        );
+        assert_eq!(
+            changes[0].old.as_ref().unwrap().actual.as_deref(),
+            Some("x")
+        );
+        assert_eq!(
+            changes[0].new.as_ref().unwrap().actual.as_deref(),
+            Some("y")
+        );
+        assert_eq!(
+            changes[1].new.as_ref().unwrap().header.as_deref(),
+            Some("via")
+        );
+    }
+
+    #[test]
+    fn other_kind_for_the_same_header_is_not_changed() {
+        let old = set(&[("a", finding("missing_header", "etag", "x"))]);
+        let new = set(&[("a", finding("unexpected_value", "etag", "x"))]);
+        assert_eq!(
+            summary(&diff(&old, &new)),
+            [("a", ChangeKind::New), ("a", ChangeKind::Resolved)]
+        );
+    }
+}
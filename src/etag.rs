@@ -0,0 +1,228 @@
+//! Parsing and checking the `etag` header of crate files.
+//!
+//! For objects stored in S3, the entity tag is the hex-encoded MD5 digest of
+//! the object, unless it was uploaded in several parts.  In that case it is
+//! the MD5 digest of the digests of the parts, followed by a dash and the
+//! number of parts.
+
+use crate::anomaly::Issue;
+use crate::check::{CheckedResponse, HeaderCheck};
+
+use reqwest::header::ETAG;
+
+/// An entity tag, as defined in RFC 7232.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EntityTag {
+    /// Whether the tag is weak, i.e. prefixed with `W/`.
+    pub weak: bool,
+    /// The tag without the quotes.
+    pub tag: String,
+}
+
+impl EntityTag {
+    /// Parse the value of an `etag` header.
+    ///
+    /// Returns `None` if the value is not a quoted string of valid characters,
+    /// optionally prefixed with `W/`.  Besides visible ASCII characters other
+    /// than `"`, RFC 7232 allows bytes from 0x80, which make up any non-ASCII
+    /// character.
+    pub fn parse(value: &str) -> Option<Self> {
+        let (weak, quoted) = match value.strip_prefix("W/") {
+            Some(rest) => (true, rest),
+            None => (false, value),
+        };
+        let tag = quoted.strip_prefix('"')?.strip_suffix('"')?;
+        if !tag
+            .bytes()
+            .all(|b| b == 0x21 || (0x23..=0x7e).contains(&b) || b >= 0x80)
+        {
+            return None;
+        }
+        Some(EntityTag {
+            weak,
+            tag: tag.to_owned(),
+        })
+    }
+
+    /// The MD5 digest of the body, in lower case, if the tag is an S3-style
+    /// digest of an object uploaded in a single part.
+    pub fn md5(&self) -> Option<String> {
+        if !self.weak && is_md5(&self.tag) {
+            Some(self.tag.to_lowercase())
+        } else {
+            None
+        }
+    }
+
+    /// The number of parts, if the tag is an S3-style tag of an object
+    /// uploaded in several parts.
+    pub fn parts(&self) -> Option<u32> {
+        let (digest, parts) = self.tag.split_once('-')?;
+        if !is_md5(digest) {
+            return None;
+        }
+        parts.parse().ok()
+    }
+}
+
+/// Whether `s` is a hex-encoded MD5 digest.
+fn is_md5(s: &str) -> bool {
+    s.len() == 32 && s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Reports `etag` headers of successful responses that are malformed, weak,
+/// or not an MD5 digest of the body.
+///
+/// Tags of multipart uploads are reported as well, since crate files are
+/// uploaded in a single part and the tag cannot be verified against the
+/// body.  Whether the digest matches the body is checked separately when
+/// downloading.
+pub struct ETagFormat;
+
+impl HeaderCheck for ETagFormat {
+    fn check(&self, response: &CheckedResponse<'_>) -> Vec<Issue> {
+        if response.status != 200 {
+            return vec![];
+        }
+        let value = match response.headers.get(ETAG) {
+            Some(value) => String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            None => return vec![],
+        };
+        let etag = match EntityTag::parse(&value) {
+            Some(etag) => etag,
+            None => {
+                return vec![issue(
+                    "malformed_etag",
+                    format!("Header 'etag' is malformed: {}.", value),
+                )]
+            }
+        };
+        if etag.weak {
+            vec![issue(
+                "weak_etag",
+                format!("Header 'etag' is a weak tag: {}.", value),
+            )]
+        } else if let Some(parts) = etag.parts() {
+            vec![issue(
+                "multipart_etag",
+                format!(
+                    "Header 'etag' is the tag of an upload in {} parts: {}.",
+                    parts, value
+                ),
+            )]
+        } else if etag.md5().is_none() {
+            vec![issue(
+                "non_md5_etag",
+                format!("Header 'etag' is not an MD5 digest: {}.", value),
+            )]
+        } else {
+            vec![]
+        }
+    }
+}
+
+fn issue(kind: &str, message: String) -> Issue {
+    Issue::Custom {
+        kind: kind.to_owned(),
+        header: Some("etag".to_owned()),
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    const MD5: &str = "b8c0fb3febcb35a8a58153ab49514b60";
+
+    fn tag(weak: bool, tag: &str) -> Option<EntityTag> {
+        Some(EntityTag {
+            weak,
+            tag: tag.to_owned(),
+        })
+    }
+
+    #[test]
+    fn strong_md5_tag() {
+        let etag = EntityTag::parse(&format!("\"{}\"", MD5)).unwrap();
+        assert_eq!(etag, tag(false, MD5).unwrap());
+        assert_eq!(etag.md5().as_deref(), Some(MD5));
+        assert_eq!(etag.parts(), None);
+    }
+
+    #[test]
+    fn uppercase_md5_is_lowercased() {
+        let etag = EntityTag::parse(&format!("\"{}\"", MD5.to_uppercase())).unwrap();
+        assert_eq!(etag.md5().as_deref(), Some(MD5));
+    }
+
+    #[test]
+    fn weak_tag_has_no_md5() {
+        let etag = EntityTag::parse(&format!("W/\"{}\"", MD5)).unwrap();
+        assert!(etag.weak);
+        assert_eq!(etag.md5(), None);
+    }
+
+    #[test]
+    fn multipart_tag() {
+        let etag = EntityTag::parse(&format!("\"{}-12\"", MD5)).unwrap();
+        assert_eq!(etag.parts(), Some(12));
+        assert_eq!(etag.md5(), None);
+        assert_eq!(EntityTag::parse("\"abc-12\"").unwrap().parts(), None);
+        assert_eq!(
+            EntityTag::parse(&format!("\"{}-x\"", MD5)).unwrap().parts(),
+            None
+        );
+    }
+
+    #[test]
+    fn malformed_tags() {
+        assert_eq!(EntityTag::parse(MD5), None);
+        assert_eq!(EntityTag::parse("\"abc"), None);
+        assert_eq!(EntityTag::parse("w/\"abc\""), None);
+        assert_eq!(EntityTag::parse("\"a\"b\""), None);
+        assert_eq!(EntityTag::parse("\"a b\""), None);
+        assert_eq!(EntityTag::parse("\""), None);
+    }
+
+    #[test]
+    fn obs_text_is_allowed() {
+        assert_eq!(EntityTag::parse("\"caf\u{e9}\""), tag(false, "caf\u{e9}"));
+        assert_eq!(EntityTag::parse("\"\""), tag(false, ""));
+    }
+
+    fn check(value: &[u8]) -> Vec<String> {
+        let mut headers = HeaderMap::new();
+        headers.insert(ETAG, HeaderValue::from_bytes(value).unwrap());
+        let response = CheckedResponse {
+            version: None,
+            url: "https://example.com/",
+            status: 200,
+            headers: &headers,
+        };
+        ETagFormat
+            .check(&response)
+            .into_iter()
+            .map(|issue| match issue {
+                Issue::Custom { kind, .. } => kind,
+                issue => panic!("unexpected issue {:?}", issue),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn format_check_reports_kinds() {
+        assert!(check(format!("\"{}\"", MD5).as_bytes()).is_empty());
+        assert_eq!(check(format!("W/\"{}\"", MD5).as_bytes()), ["weak_etag"]);
+        assert_eq!(
+            check(format!("\"{}-2\"", MD5).as_bytes()),
+            ["multipart_etag"]
+        );
+        assert_eq!(check(MD5.as_bytes()), ["malformed_etag"]);
+        assert_eq!(check(b"\"a b\""), ["malformed_etag"]);
+        assert_eq!(check(b"\"abc\""), ["non_md5_etag"]);
+        assert_eq!(check(b"\"caf\xe9\""), ["non_md5_etag"]);
+    }
+}
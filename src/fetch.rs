@@ -0,0 +1,509 @@
+//! Requesting crate files and checking the responses.
+
+use crate::anomaly::{Anomaly, Issue};
+use crate::check::{CheckedResponse, Checks};
+use crate::etag::EntityTag;
+use crate::index::Version;
+use crate::throttle::RateLimiter;
+
+use chrono::{DateTime, Utc};
+use md5::Md5;
+use rand::Rng;
+use reqwest::header::{HeaderMap, ACCEPT_ENCODING, CONTENT_LENGTH, ETAG};
+use reqwest::{Client, RequestBuilder, Response};
+use sha2::{Digest, Sha256};
+
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// The upper limit for the delay between two attempts.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// The default time limit for a request, including reading the body.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The upper limit for the time it takes to establish a connection.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Build an HTTP client giving up on requests after `timeout`.
+fn client(timeout: Duration) -> Client {
+    Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT.min(timeout))
+        .timeout(timeout)
+        .build()
+        .expect("cannot initialize the HTTP client")
+}
+
+/// How often and how quickly failed requests are retried.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// The number of retries after the first attempt.
+    pub retries: u32,
+    /// The delay before the first retry, doubled for every further retry.
+    pub base_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// The delay before the given retry, starting at 1.
+    ///
+    /// The delay grows exponentially, and a random amount of up to half of it
+    /// is subtracted, so workers that failed at the same time don't all retry
+    /// at the same time.
+    fn delay(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry - 1);
+        let delay = self.base_delay.saturating_mul(factor).min(MAX_RETRY_DELAY);
+        let jitter = rand::thread_rng().gen_range(0.0..=0.5);
+        delay.mul_f64(1.0 - jitter)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            retries: 3,
+            base_delay: Duration::from_millis(500),
+        }
+    }
+}
+
+/// The category of a failed request, used to decide whether to retry it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ErrorClass {
+    /// The host name could not be resolved.
+    Dns,
+    /// The connection could not be established, or was reset.
+    Connect,
+    /// The request timed out.
+    Timeout,
+    /// The TLS handshake or certificate validation failed.
+    Tls,
+    /// The server responded with a 5xx status code.
+    ServerError,
+    /// Any other error.
+    Other,
+}
+
+impl ErrorClass {
+    /// Classify an error returned by `reqwest`.
+    fn of(error: &reqwest::Error) -> Self {
+        if error.is_timeout() {
+            return ErrorClass::Timeout;
+        }
+        if error
+            .status()
+            .is_some_and(|status| status.is_server_error())
+        {
+            return ErrorClass::ServerError;
+        }
+        // The underlying hyper and TLS errors are not exposed in a structured
+        // way, so look at the messages of the error chain.  The message of
+        // `error` itself is skipped, since it contains the URL, and with it
+        // the crate name.
+        let messages = error.source().map(describe).unwrap_or_default();
+        Self::from_messages(&messages, error.is_connect())
+    }
+
+    /// Classify an error that is not a timeout or a server error by the
+    /// messages of its sources.
+    fn from_messages(messages: &str, is_connect: bool) -> Self {
+        let messages = messages.to_lowercase();
+        if messages.contains("dns error") || messages.contains("failed to lookup address") {
+            ErrorClass::Dns
+        } else if ["tls", "ssl", "certificate", "handshake"]
+            .iter()
+            .any(|s| messages.contains(s))
+        {
+            ErrorClass::Tls
+        } else if is_connect
+            || messages.contains("connection refused")
+            || messages.contains("connection reset")
+        {
+            ErrorClass::Connect
+        } else {
+            ErrorClass::Other
+        }
+    }
+
+    /// Whether errors of this class are likely to go away when retrying.
+    pub fn is_transient(self) -> bool {
+        match self {
+            ErrorClass::Dns
+            | ErrorClass::Connect
+            | ErrorClass::Timeout
+            | ErrorClass::ServerError => true,
+            ErrorClass::Tls | ErrorClass::Other => false,
+        }
+    }
+
+    /// A short, stable identifier for the class.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorClass::Dns => "dns",
+            ErrorClass::Connect => "connect",
+            ErrorClass::Timeout => "timeout",
+            ErrorClass::Tls => "tls",
+            ErrorClass::ServerError => "http_5xx",
+            ErrorClass::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for ErrorClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The response to a request for a crate file, as observed by the fetcher.
+#[derive(Clone, Debug)]
+pub struct Observation {
+    /// The HTTP status code.
+    pub status: u16,
+    /// The response headers.
+    pub headers: HeaderMap,
+    /// The time it took to receive the response headers.
+    pub elapsed: Duration,
+    /// The time the request was sent.
+    pub fetched_at: DateTime<Utc>,
+}
+
+/// The result of checking a single version of a crate.
+pub struct Outcome {
+    /// The version that was checked.
+    pub version: Version,
+    /// The URL of the crate file.
+    pub url: String,
+    /// The number of requests it took to get a response, or to give up.
+    pub attempts: u32,
+    /// The response that was checked, or `None` if the request failed.
+    pub observation: Option<Observation>,
+    /// The size of the crate file, if it was downloaded completely.
+    pub size: Option<u64>,
+    /// The anomalies found, if any.
+    pub anomalies: Vec<Anomaly>,
+}
+
+/// Fetches crate files and runs the header checks on the responses.
+pub struct Fetcher {
+    client: Client,
+    checks: Checks,
+    download: bool,
+    retry: RetryPolicy,
+    rate_limiter: Option<RateLimiter>,
+}
+
+impl Fetcher {
+    /// Create a new fetcher.
+    ///
+    /// If `download` is true, the full crate file is downloaded and verified
+    /// against the checksum in the index; otherwise only a `HEAD` request is
+    /// made.  All requests, including retries, are subject to the rate
+    /// limiter, if given.  Requests time out after [`DEFAULT_TIMEOUT`].
+    pub fn new(
+        checks: impl Into<Checks>,
+        download: bool,
+        retry: RetryPolicy,
+        rate_limiter: Option<RateLimiter>,
+    ) -> Self {
+        Fetcher {
+            client: client(DEFAULT_TIMEOUT),
+            checks: checks.into(),
+            download,
+            retry,
+            rate_limiter,
+        }
+    }
+
+    /// Give up on requests after `timeout`, including reading the body.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.client = client(timeout);
+        self
+    }
+
+    /// Request the crate file for `version` from `url` and check the response.
+    ///
+    /// Requests failing with a transient error are retried according to the
+    /// retry policy.
+    pub async fn get_and_check(&self, version: &Version, url: String) -> Outcome {
+        let (attempts, result) = self.send(|| self.request(&url)).await;
+        let mut size = None;
+        let (observation, anomalies) = match result {
+            Ok((mut response, observation)) => {
+                let mut anomalies = self.checks.check_version(
+                    version,
+                    &url,
+                    observation.status,
+                    &observation.headers,
+                );
+                // Error pages are not crate files, so only the body of a
+                // successful response is verified.
+                if self.download && observation.status == 200 {
+                    let (received, body_anomalies) = verify_body(version, &mut response).await;
+                    size = received;
+                    anomalies.extend(body_anomalies);
+                }
+                (Some(observation), anomalies)
+            }
+            Err(error) => (
+                None,
+                vec![Anomaly::RequestFailed {
+                    version: version.clone(),
+                    error: describe(&error),
+                    class: ErrorClass::of(&error),
+                    attempts,
+                }],
+            ),
+        };
+        Outcome {
+            version: version.clone(),
+            url,
+            attempts,
+            observation,
+            size,
+            anomalies,
+        }
+    }
+
+    /// Request the sparse index file of the crate `name` from `url` and check
+    /// the response headers.
+    ///
+    /// Like Cargo, the request asks for a compressed response.  The body is
+    /// not read.
+    pub async fn check_index_file(&self, name: &str, url: &str) -> Vec<Anomaly> {
+        let (attempts, result) = self
+            .send(|| self.request(url).header(ACCEPT_ENCODING, "gzip"))
+            .await;
+        let issues = match result {
+            Ok((_, observation)) => self.checks.check(&CheckedResponse {
+                version: None,
+                url,
+                status: observation.status,
+                headers: &observation.headers,
+            }),
+            Err(error) => vec![Issue::RequestFailed {
+                error: describe(&error),
+                class: ErrorClass::of(&error),
+                attempts,
+            }],
+        };
+        issues
+            .into_iter()
+            .map(|issue| Anomaly::IndexFile {
+                name: name.to_owned(),
+                url: url.to_owned(),
+                issue,
+            })
+            .collect()
+    }
+
+    /// Download the file at `url` without checking the response, e.g. a file
+    /// of a sparse index.
+    ///
+    /// Like all other requests, the request is subject to the rate limiter,
+    /// and retried according to the retry policy.
+    pub async fn fetch(&self, url: &str) -> Result<Vec<u8>, String> {
+        let (_, result) = self.send(|| self.client.get(url)).await;
+        let (response, observation) = result.map_err(|e| describe(&e))?;
+        match observation.status {
+            200 => {}
+            404 | 410 => return Err("not found".to_owned()),
+            status => return Err(format!("unexpected status {}", status)),
+        }
+        let body = response.bytes().await.map_err(|e| describe(&e))?;
+        Ok(body.to_vec())
+    }
+
+    /// A `GET` request for `url` if downloading, and a `HEAD` request otherwise.
+    fn request(&self, url: &str) -> RequestBuilder {
+        if self.download {
+            self.client.get(url)
+        } else {
+            self.client.head(url)
+        }
+    }
+
+    /// Send the request built by `request`, retrying transient errors, and
+    /// return the number of attempts along with the response.
+    async fn send<F>(&self, request: F) -> (u32, Result<(Response, Observation), reqwest::Error>)
+    where
+        F: Fn() -> RequestBuilder,
+    {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            if let Some(rate_limiter) = &self.rate_limiter {
+                rate_limiter.acquire().await;
+            }
+            let fetched_at = Utc::now();
+            let started = Instant::now();
+            let result = request().send().await.and_then(|response| {
+                if response.status().is_server_error() {
+                    response.error_for_status()
+                } else {
+                    Ok(response)
+                }
+            });
+            let error = match result {
+                Ok(response) => {
+                    let observation = Observation {
+                        status: response.status().as_u16(),
+                        headers: response.headers().clone(),
+                        elapsed: started.elapsed(),
+                        fetched_at,
+                    };
+                    return (attempts, Ok((response, observation)));
+                }
+                Err(e) => e,
+            };
+            if ErrorClass::of(&error).is_transient() && attempts <= self.retry.retries {
+                tokio::time::sleep(self.retry.delay(attempts)).await;
+                continue;
+            }
+            return (attempts, Err(error));
+        }
+    }
+}
+
+/// Stream the response body through SHA-256 and MD5, and compare the results
+/// with the checksum in the index and the digest in the `etag` header.
+///
+/// The response must be successful.  Returns the size of the body if it was
+/// received completely.
+async fn verify_body(version: &Version, response: &mut Response) -> (Option<u64>, Vec<Anomaly>) {
+    let content_length = response
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.parse().ok());
+    let etag_md5 = response
+        .headers()
+        .get(ETAG)
+        .and_then(|value| EntityTag::parse(value.to_str().ok()?)?.md5());
+    let mut sha256 = Sha256::new();
+    let mut md5 = Md5::new();
+    let mut received = 0;
+    loop {
+        match response.chunk().await {
+            Ok(None) => break,
+            Ok(Some(chunk)) => {
+                sha256.update(&chunk);
+                md5.update(&chunk);
+                received += chunk.len() as u64;
+            }
+            Err(e) => {
+                let anomaly = Anomaly::TruncatedBody {
+                    version: version.clone(),
+                    content_length,
+                    received,
+                    error: describe(&e),
+                };
+                return (None, vec![anomaly]);
+            }
+        }
+    }
+    let mut anomalies = vec![];
+    if let Some(content_length) = content_length {
+        if content_length != received {
+            anomalies.push(Anomaly::ContentLengthMismatch {
+                version: version.clone(),
+                content_length,
+                received,
+            });
+        }
+    }
+    if let Some(cksum) = &version.cksum {
+        let checksum = format!("{:x}", sha256.finalize());
+        if &checksum != cksum {
+            anomalies.push(Anomaly::ChecksumMismatch {
+                version: version.clone(),
+                expected: cksum.clone(),
+                actual: checksum,
+            });
+        }
+    }
+    if let Some(etag_md5) = etag_md5 {
+        let digest = format!("{:x}", md5.finalize());
+        if digest != etag_md5 {
+            anomalies.push(Anomaly::ETagMismatch {
+                version: version.clone(),
+                expected: etag_md5,
+                actual: digest,
+            });
+        }
+    }
+    (Some(received), anomalies)
+}
+
+/// The message of an error, followed by the messages of all its sources.
+fn describe(error: &dyn Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(e) = source {
+        message += &format!(": {}", e);
+        source = e.source();
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_are_classified_by_message() {
+        let cases = [
+            (
+                "dns error: failed to lookup address information",
+                false,
+                ErrorClass::Dns,
+            ),
+            (
+                "invalid peer certificate: UnknownIssuer",
+                true,
+                ErrorClass::Tls,
+            ),
+            (
+                "received fatal alert: HandshakeFailure",
+                false,
+                ErrorClass::Tls,
+            ),
+            (
+                "tcp connect error: Connection refused (os error 111)",
+                false,
+                ErrorClass::Connect,
+            ),
+            ("connection reset by peer", false, ErrorClass::Connect),
+            ("", true, ErrorClass::Connect),
+            ("error decoding response body", false, ErrorClass::Other),
+        ];
+        for (messages, is_connect, class) in cases {
+            assert_eq!(
+                ErrorClass::from_messages(messages, is_connect),
+                class,
+                "{}",
+                messages
+            );
+        }
+    }
+
+    #[test]
+    fn only_transient_classes_are_retried() {
+        assert!(ErrorClass::Dns.is_transient());
+        assert!(ErrorClass::Connect.is_transient());
+        assert!(ErrorClass::Timeout.is_transient());
+        assert!(ErrorClass::ServerError.is_transient());
+        assert!(!ErrorClass::Tls.is_transient());
+        assert!(!ErrorClass::Other.is_transient());
+    }
+
+    #[tokio::test]
+    async fn crate_name_in_url_does_not_affect_class() {
+        // Nothing listens on port 1, so the connection is refused.
+        let client = Client::new();
+        for name in ["serde", "rustls", "openssl", "tls-handshake-certificate"] {
+            let url = format!("http://127.0.0.1:1/{0}/{0}-1.0.0.crate", name);
+            let error = client.get(&url).send().await.unwrap_err();
+            assert_eq!(ErrorClass::of(&error), ErrorClass::Connect, "{}", name);
+        }
+    }
+}
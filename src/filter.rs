@@ -0,0 +1,65 @@
+//! Selecting the crates and versions to check.
+
+use crate::index::Version;
+
+use glob::Pattern;
+use regex::Regex;
+use semver::VersionReq;
+use sha2::{Digest, Sha256};
+
+use std::collections::HashSet;
+use std::convert::TryInto;
+
+/// The criteria a version has to meet to be checked.
+///
+/// All criteria that are set must be met; by default, all versions are
+/// checked.
+#[derive(Default)]
+pub struct Filter {
+    /// Crate names to check; all crates if empty.
+    pub names: HashSet<String>,
+    /// A glob pattern crate names must match.
+    pub glob: Option<Pattern>,
+    /// A regular expression crate names must match.
+    pub regex: Option<Regex>,
+    /// The versions to check; versions that aren't valid semver never match.
+    pub versions: Option<VersionReq>,
+    /// Whether to skip yanked versions.
+    pub skip_yanked: bool,
+    /// The fraction of versions to check, chosen at random.
+    pub sample: Option<f64>,
+    /// The seed selecting the sample.
+    pub seed: u64,
+}
+
+impl Filter {
+    /// Whether versions of the crate `name` may be checked.
+    pub fn matches_name(&self, name: &str) -> bool {
+        (self.names.is_empty() || self.names.contains(name))
+            && self.glob.as_ref().is_none_or(|glob| glob.matches(name))
+            && self.regex.as_ref().is_none_or(|regex| regex.is_match(name))
+    }
+
+    /// Whether the version is to be checked.
+    ///
+    /// The sample is chosen by hashing the name and version with the seed,
+    /// so the same seed always selects the same versions, e.g. when resuming
+    /// or repeating a run.
+    pub fn matches(&self, version: &Version) -> bool {
+        self.matches_name(&version.name)
+            && !(self.skip_yanked && version.yanked)
+            && self.versions.as_ref().is_none_or(|req| {
+                semver::Version::parse(&version.vers).is_ok_and(|vers| req.matches(&vers))
+            })
+            && self.sample.is_none_or(|fraction| {
+                let hash = Sha256::new()
+                    .chain_update(self.seed.to_le_bytes())
+                    .chain_update(&version.name)
+                    .chain_update([0])
+                    .chain_update(&version.vers)
+                    .finalize();
+                let value = u64::from_le_bytes(hash[..8].try_into().unwrap());
+                (value as f64 / u64::MAX as f64) < fraction
+            })
+    }
+}
@@ -0,0 +1,276 @@
+//! Reading crate versions from the git history of the registry index.
+
+use crate::config::RegistryConfig;
+use crate::index::{parse_version, parse_versions, IndexError, Version};
+use crate::publish::PublishTimes;
+
+use chrono::DateTime;
+use git2::{DiffOptions, ObjectType, Oid, Repository, Tree, TreeWalkMode, TreeWalkResult};
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Look up the tree of the revision `rev`.
+fn open_tree<'a>(repo: &'a Repository, rev: &str) -> Result<Tree<'a>, git2::Error> {
+    repo.revparse_single(rev)?.peel_to_tree()
+}
+
+/// Format a git error for the given revision.
+fn describe(rev: &str, e: git2::Error) -> String {
+    format!(
+        "cannot read revision '{}' of the index: {}",
+        rev,
+        e.message()
+    )
+}
+
+/// Read the registry configuration from the tree of `rev`.
+pub fn read_config<P: AsRef<Path>>(index_root: P, rev: &str) -> Result<RegistryConfig, String> {
+    let repo = Repository::open(index_root).map_err(|e| describe(rev, e))?;
+    let tree = open_tree(&repo, rev).map_err(|e| describe(rev, e))?;
+    let blob = tree
+        .get_name("config.json")
+        .ok_or_else(|| format!("revision '{}' of the index has no config.json", rev))?
+        .to_object(&repo)
+        .and_then(|object| object.peel_to_blob())
+        .map_err(|e| describe(rev, e))?;
+    let contents = String::from_utf8_lossy(blob.content());
+    RegistryConfig::parse(&contents, &format!("{}:config.json", rev))
+}
+
+/// Iterate over all versions of all crates in the tree of `rev` in the
+/// repository at `index_root`, which may be a bare clone.
+///
+/// The paths in errors are relative to `index_root`, as if the revision was
+/// checked out there.
+pub fn iter_versions_at<P: AsRef<Path>>(
+    index_root: P,
+    rev: &str,
+) -> Result<impl Iterator<Item = Result<Version, IndexError>>, String> {
+    let index_root = index_root.as_ref().to_owned();
+    let repo = Repository::open(&index_root).map_err(|e| describe(rev, e))?;
+    let mut files: Vec<(PathBuf, Oid)> = vec![];
+    open_tree(&repo, rev)
+        .and_then(|tree| {
+            tree.walk(TreeWalkMode::PreOrder, |dir, entry| {
+                let name = entry.name().unwrap_or_default();
+                if name.starts_with('.') {
+                    return TreeWalkResult::Skip;
+                }
+                if entry.kind() == Some(ObjectType::Blob)
+                    && !(dir.is_empty() && name == "config.json")
+                {
+                    files.push((index_root.join(dir).join(name), entry.id()));
+                }
+                TreeWalkResult::Ok
+            })
+        })
+        .map_err(|e| describe(rev, e))?;
+    Ok(files
+        .into_iter()
+        .flat_map(move |(path, oid)| match repo.find_blob(oid) {
+            Ok(blob) => parse_versions(&path, blob.content()),
+            Err(e) => vec![Err(IndexError {
+                path,
+                line: None,
+                error: e.message().to_owned(),
+            })],
+        }))
+}
+
+/// Find the versions in the repository at `index_root` that were added or
+/// changed since the revision `since`.
+///
+/// The revision is compared with `rev` if given, and with the working tree
+/// otherwise, so versions added by uncommitted changes are included as well.
+/// A changed line is returned as a whole, e.g. when a version was yanked.
+pub fn changed_versions<P: AsRef<Path>>(
+    index_root: P,
+    since: &str,
+    rev: Option<&str>,
+) -> Result<Vec<Result<Version, IndexError>>, String> {
+    let index_root = index_root.as_ref();
+    let error =
+        |e: git2::Error| format!("cannot diff the index against '{}': {}", since, e.message());
+    let repo = Repository::open(index_root).map_err(error)?;
+    let base = open_tree(&repo, since).map_err(error)?;
+    let mut options = DiffOptions::new();
+    options.context_lines(0);
+    let diff = match rev {
+        Some(rev) => {
+            let tree = open_tree(&repo, rev).map_err(|e| describe(rev, e))?;
+            repo.diff_tree_to_tree(Some(&base), Some(&tree), Some(&mut options))
+        }
+        None => repo.diff_tree_to_workdir_with_index(Some(&base), Some(&mut options)),
+    }
+    .map_err(error)?;
+    let mut versions = vec![];
+    diff.foreach(
+        &mut |_, _| true,
+        None,
+        None,
+        Some(&mut |delta, _, line| {
+            let path = match delta.new_file().path() {
+                Some(path) => path,
+                None => return true,
+            };
+            if line.origin() != '+' || path == Path::new("config.json") {
+                return true;
+            }
+            let contents = line.content();
+            if !contents.iter().all(u8::is_ascii_whitespace) {
+                let line_number = line.new_lineno().unwrap_or_default() as usize;
+                versions.push(parse_version(&index_root.join(path), line_number, contents));
+            }
+            true
+        }),
+    )
+    .map_err(error)?;
+    Ok(versions)
+}
+
+/// Find the time each version was published from the history of `rev` in the
+/// repository at `index_root`, taking the time of the commit that added it to
+/// the index.
+///
+/// Only the first parent of merge commits is followed.  Lines that were only
+/// changed, e.g. by yanking, don't count as an addition.  Versions already
+/// present in the root commit are not included, since the history of the
+/// crates.io index is squashed from time to time.
+pub fn publish_times<P: AsRef<Path>>(index_root: P, rev: &str) -> Result<PublishTimes, String> {
+    let error = |e: git2::Error| describe(rev, e);
+    let repo = Repository::open(index_root).map_err(error)?;
+    let mut walk = repo.revwalk().map_err(error)?;
+    let head = repo
+        .revparse_single(rev)
+        .and_then(|object| object.peel_to_commit())
+        .map_err(error)?;
+    walk.push(head.id()).map_err(error)?;
+    walk.simplify_first_parent().map_err(error)?;
+    let mut options = DiffOptions::new();
+    options.context_lines(0);
+    let mut times = PublishTimes::new();
+    for oid in walk {
+        let commit = repo.find_commit(oid.map_err(error)?).map_err(error)?;
+        let parent = match commit.parents().next() {
+            Some(parent) => parent,
+            None => continue,
+        };
+        let time = match DateTime::from_timestamp(commit.time().seconds(), 0) {
+            Some(time) => time,
+            None => continue,
+        };
+        let diff = repo
+            .diff_tree_to_tree(
+                Some(&parent.tree().map_err(error)?),
+                Some(&commit.tree().map_err(error)?),
+                Some(&mut options),
+            )
+            .map_err(error)?;
+        // Versions that appear on both a removed and an added line were only
+        // changed, e.g. yanked, and not published in this commit.
+        let mut added = vec![];
+        let mut removed = HashSet::new();
+        diff.foreach(
+            &mut |_, _| true,
+            None,
+            None,
+            Some(&mut |delta, _, line| {
+                let path = match line.origin() {
+                    '+' => delta.new_file().path(),
+                    '-' => delta.old_file().path(),
+                    _ => return true,
+                };
+                let path = match path {
+                    Some(path) if path != Path::new("config.json") => path,
+                    _ => return true,
+                };
+                if let Ok(version) = parse_version(path, 0, line.content()) {
+                    let key = (version.name, version.vers);
+                    if line.origin() == '+' {
+                        added.push(key);
+                    } else {
+                        removed.insert(key);
+                    }
+                }
+                true
+            }),
+        )
+        .map_err(error)?;
+        // The history is walked from the newest commit, so the time of the
+        // earliest addition is kept.
+        for key in added {
+            if !removed.contains(&key) {
+                times.insert(key, time);
+            }
+        }
+    }
+    Ok(times)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use git2::{Signature, Time};
+
+    /// Commit a tree holding the index file of `serde` with the given lines
+    /// at the given time.
+    fn commit(repo: &Repository, time: i64, lines: &[&str]) {
+        let mut contents = String::new();
+        for line in lines {
+            contents += line;
+            contents += "\n";
+        }
+        let blob = repo.blob(contents.as_bytes()).unwrap();
+        let mut builder = repo.treebuilder(None).unwrap();
+        builder.insert("serde", blob, 0o100644).unwrap();
+        let rd = builder.write().unwrap();
+        let mut builder = repo.treebuilder(None).unwrap();
+        builder.insert("rd", rd, 0o040000).unwrap();
+        let se = builder.write().unwrap();
+        let mut builder = repo.treebuilder(None).unwrap();
+        builder.insert("se", se, 0o040000).unwrap();
+        let config = repo.blob(b"{\"dl\": \"https://example.com\"}").unwrap();
+        builder.insert("config.json", config, 0o100644).unwrap();
+        let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+        let signature = Signature::new("test", "test@example.com", &Time::new(time, 0)).unwrap();
+        let parent = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "update",
+            &tree,
+            parent.as_ref().into_iter().collect::<Vec<_>>().as_slice(),
+        )
+        .unwrap();
+    }
+
+    fn line(vers: &str, yanked: bool) -> String {
+        format!(
+            r#"{{"name":"serde","vers":"{}","deps":[],"cksum":"abc","features":{{}},"yanked":{}}}"#,
+            vers, yanked
+        )
+    }
+
+    #[test]
+    fn yanking_is_not_publication() {
+        let dir = std::env::temp_dir().join(format!("publish-times-{}", std::process::id()));
+        let repo = Repository::init_bare(&dir).unwrap();
+        commit(&repo, 1000, &[&line("1.0.0", false)]);
+        commit(&repo, 2000, &[&line("1.0.0", false), &line("1.0.1", false)]);
+        commit(&repo, 3000, &[&line("1.0.0", true), &line("1.0.1", false)]);
+        commit(&repo, 4000, &[&line("1.0.0", true), &line("1.0.1", true)]);
+        let times = publish_times(&dir, "HEAD");
+        std::fs::remove_dir_all(&dir).unwrap();
+        let times: Vec<_> = times.unwrap().into_iter().collect();
+        assert_eq!(
+            times,
+            [(
+                ("serde".to_owned(), "1.0.1".to_owned()),
+                DateTime::from_timestamp(2000, 0).unwrap()
+            )]
+        );
+    }
+}
@@ -0,0 +1,214 @@
+//! Reading crate versions from a checkout of the registry index.
+
+use serde::{Deserialize, Deserializer, Serialize};
+use walkdir::WalkDir;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// A single version of a crate, as listed in the index.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Version {
+    /// The name of the crate.
+    pub name: String,
+    /// The version number.
+    pub vers: String,
+    /// The dependencies of this version.
+    #[serde(default)]
+    pub deps: Vec<Dependency>,
+    /// The SHA-256 checksum of the crate file, hex-encoded; only missing for
+    /// packages from lock files without checksums.  It is required in index
+    /// entries.
+    #[serde(
+        deserialize_with = "deserialize_cksum",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub cksum: Option<String>,
+    /// The features of this version, with the features they enable.
+    #[serde(default)]
+    pub features: BTreeMap<String, Vec<String>>,
+    /// Features using the newer feature syntax, only present in schema version 2.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub features2: Option<BTreeMap<String, Vec<String>>>,
+    /// Whether the version has been yanked.
+    #[serde(default)]
+    pub yanked: bool,
+    /// The value of the `links` field in the manifest.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub links: Option<String>,
+    /// The schema version of the entry; absent for version 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub v: Option<u32>,
+    /// The minimum supported Rust version.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rust_version: Option<String>,
+    /// Fields not known to this tool, preserved as they are.
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
+}
+
+/// Deserialize the checksum of an index entry, which must be present.
+fn deserialize_cksum<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    String::deserialize(deserializer).map(Some)
+}
+
+impl Version {
+    /// The schema version of the index entry.
+    pub fn schema_version(&self) -> u32 {
+        self.v.unwrap_or(1)
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.yanked {
+            write!(f, "{} ({}, yanked)", self.name, self.vers)
+        } else {
+            write!(f, "{} ({})", self.name, self.vers)
+        }
+    }
+}
+
+/// A dependency of a crate version, as listed in the index.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Dependency {
+    /// The name of the dependency, or the name it was renamed to.
+    pub name: String,
+    /// The version requirement.
+    pub req: String,
+    /// The features enabled for the dependency.
+    #[serde(default)]
+    pub features: Vec<String>,
+    /// Whether the dependency is optional.
+    #[serde(default)]
+    pub optional: bool,
+    /// Whether the default features of the dependency are enabled.
+    #[serde(default = "default_true")]
+    pub default_features: bool,
+    /// The target platform the dependency is restricted to, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// One of `normal`, `dev` or `build`; absent means `normal`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    /// The index URL of the registry of the dependency, if not the same.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registry: Option<String>,
+    /// The actual name of the crate if the dependency was renamed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
+    /// Fields not known to this tool, preserved as they are.
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// An index entry that could not be read or parsed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IndexError {
+    /// The file the entry is in.
+    pub path: PathBuf,
+    /// The line number of the entry, or `None` if the whole file is affected.
+    pub line: Option<usize>,
+    /// The error message.
+    pub error: String,
+}
+
+impl fmt::Display for IndexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path.display(), line, self.error),
+            None => write!(f, "{}: {}", self.path.display(), self.error),
+        }
+    }
+}
+
+/// Iterate over all versions of all crates in the index checkout at `index_root`.
+///
+/// Entries that cannot be read or parsed are returned as errors, so a single
+/// broken line does not end the iteration.
+pub fn iter_versions<P: AsRef<Path>>(
+    index_root: P,
+) -> impl Iterator<Item = Result<Version, IndexError>> {
+    let root = index_root.as_ref().to_owned();
+    WalkDir::new(index_root)
+        .into_iter()
+        .filter_entry(|entry| {
+            let file_name = entry.file_name().to_string_lossy();
+            file_name != ".git" && file_name != "config.json"
+        })
+        .filter(|entry| {
+            entry
+                .as_ref()
+                .map_or(true, |entry| entry.file_type().is_file())
+        })
+        .flat_map(move |entry| match entry {
+            Ok(entry) => read_versions(entry.path()),
+            Err(e) => vec![Err(IndexError {
+                path: e.path().unwrap_or(&root).to_owned(),
+                line: None,
+                error: e.to_string(),
+            })],
+        })
+}
+
+/// Read all versions listed in a single index file.
+fn read_versions(path: &Path) -> Vec<Result<Version, IndexError>> {
+    let contents = match std::fs::read(path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            return vec![Err(IndexError {
+                path: path.to_owned(),
+                line: None,
+                error: e.to_string(),
+            })]
+        }
+    };
+    parse_versions(path, &contents)
+}
+
+/// Parse the contents of an index file, with one version per line.
+pub fn parse_versions(path: &Path, contents: &[u8]) -> Vec<Result<Version, IndexError>> {
+    contents
+        .split(|&b| b == b'\n')
+        .enumerate()
+        .filter(|(_, line)| !line.iter().all(u8::is_ascii_whitespace))
+        .map(|(i, line)| parse_version(path, i + 1, line))
+        .collect()
+}
+
+/// Parse a single line of an index file.
+pub fn parse_version(path: &Path, line: usize, contents: &[u8]) -> Result<Version, IndexError> {
+    serde_json::from_slice(contents).map_err(|e| IndexError {
+        path: path.to_owned(),
+        line: Some(line),
+        error: e.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<Version, IndexError> {
+        parse_version(Path::new("se/rd/serde"), 1, line.as_bytes())
+    }
+
+    #[test]
+    fn checksum_is_required() {
+        let version =
+            parse(r#"{"name":"serde","vers":"1.0.0","deps":[],"cksum":"abc","features":{}}"#)
+                .unwrap();
+        assert_eq!(version.cksum.as_deref(), Some("abc"));
+        let error =
+            parse(r#"{"name":"serde","vers":"1.0.0","deps":[],"features":{}}"#).unwrap_err();
+        assert!(error.error.contains("missing field `cksum`"), "{}", error);
+        assert!(parse(r#"{"name":"serde","vers":"1.0.0","cksum":null,"features":{}}"#).is_err());
+    }
+}
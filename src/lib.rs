@@ -0,0 +1,58 @@
+//! Detect anomalies in the HTTP headers of crate downloads from crates.io.
+//!
+//! The library provides the building blocks of the `crates-io-header-anomalies`
+//! binary, so the checks can be embedded in other services:
+//!
+//! - [`iter_versions`] and the [`git`], [`sparse`] and [`lockfile`] modules
+//!   read the versions to check from a registry index or a lock file.
+//! - [`Rules`] describes the expected response headers, and is turned into
+//!   [`Checks`], a list of [`HeaderCheck`] implementations that custom checks
+//!   can be added to.
+//! - [`Fetcher`] requests crate files, retrying transient failures, and
+//!   checks the responses, producing an [`Outcome`] with a list of
+//!   [`Anomaly`] values.
+//!
+//! ```no_run
+//! use crates_io_header_anomalies::{iter_versions, DownloadTemplate, Fetcher, RetryPolicy, Rules};
+//!
+//! # async fn run() {
+//! let template = DownloadTemplate::new("https://static.crates.io/crates");
+//! let fetcher = Fetcher::new(Rules::default(), false, RetryPolicy::default(), None);
+//! for version in iter_versions("crates.io-index").filter_map(Result::ok) {
+//!     let outcome = fetcher.get_and_check(&version, template.url(&version)).await;
+//!     for anomaly in &outcome.anomalies {
+//!         println!("{}", anomaly);
+//!     }
+//! }
+//! # }
+//! ```
+
+#![warn(missing_docs)]
+
+pub mod anomaly;
+pub mod baseline;
+pub mod check;
+pub mod checkpoint;
+pub mod config;
+pub mod db;
+pub mod diff;
+pub mod etag;
+pub mod fetch;
+pub mod filter;
+pub mod git;
+pub mod index;
+pub mod lockfile;
+pub mod output;
+pub mod publish;
+pub mod rules;
+pub mod size;
+pub mod sparse;
+pub mod throttle;
+
+pub use anomaly::{Anomaly, Finding, Issue};
+pub use check::{CheckedResponse, Checks, HeaderCheck};
+pub use config::{DownloadTemplate, RegistryConfig};
+pub use fetch::{ErrorClass, Fetcher, Observation, Outcome, RetryPolicy};
+pub use index::{iter_versions, IndexError, Version};
+pub use rules::Rules;
+pub use throttle::RateLimiter;
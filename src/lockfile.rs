@@ -0,0 +1,193 @@
+//! Reading the crates.io packages of a `Cargo.lock` file.
+
+use crate::index::Version;
+
+use serde::Deserialize;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+/// The download URL of crates.io, used when checking lock files without an
+/// index.
+pub const CRATES_IO_DL: &str = "https://static.crates.io/crates";
+
+/// The sources of crates.io packages in lock files, via the git and the
+/// sparse index.
+const CRATES_IO_SOURCES: [&str; 2] = [
+    "registry+https://github.com/rust-lang/crates.io-index",
+    "sparse+https://index.crates.io/",
+];
+
+/// The name, version number and checksum of a package.
+type PackageId = (String, String, Option<String>);
+
+#[derive(Deserialize)]
+struct Lockfile {
+    #[serde(default, rename = "package")]
+    packages: Vec<Package>,
+    /// Checksums of lock file format version 1, keyed by
+    /// `checksum <name> <version> (<source>)`.
+    #[serde(default)]
+    metadata: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct Package {
+    name: String,
+    version: String,
+    source: Option<String>,
+    checksum: Option<String>,
+}
+
+/// The packages read from lock files.
+#[derive(Debug, Default)]
+pub struct LockedPackages {
+    /// The crates.io packages, each listed once.  The versions only have their
+    /// name, version number and checksum set.
+    pub versions: Vec<Version>,
+    /// The number of packages from other registries, by source.  These are
+    /// not checked, since their download URL is not known.
+    pub unsupported: BTreeMap<String, usize>,
+}
+
+/// Read the crates.io packages listed in the lock files at `paths`.
+pub fn read_lockfiles<P: AsRef<Path>>(paths: &[P]) -> Result<LockedPackages, String> {
+    let mut packages = BTreeSet::new();
+    let mut unsupported = BTreeMap::new();
+    for path in paths {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read lock file {}: {}", path.display(), e))?;
+        parse_lockfile(&contents, &mut packages, &mut unsupported)
+            .map_err(|e| format!("invalid lock file {}: {}", path.display(), e))?;
+    }
+    Ok(LockedPackages {
+        versions: packages
+            .into_iter()
+            .map(|(name, vers, cksum)| Version {
+                name,
+                vers,
+                cksum,
+                ..Version::default()
+            })
+            .collect(),
+        unsupported,
+    })
+}
+
+/// Add the crates.io packages in the lock file `contents` to `packages`, and
+/// count the packages from other registries in `unsupported`.
+fn parse_lockfile(
+    contents: &str,
+    packages: &mut BTreeSet<PackageId>,
+    unsupported: &mut BTreeMap<String, usize>,
+) -> Result<(), toml::de::Error> {
+    let Lockfile {
+        packages: locked,
+        metadata,
+    } = toml::from_str(contents)?;
+    for package in locked {
+        let source = match &package.source {
+            Some(source) if CRATES_IO_SOURCES.contains(&source.as_str()) => source,
+            Some(source) if source.starts_with("registry+") || source.starts_with("sparse+") => {
+                *unsupported.entry(source.clone()).or_default() += 1;
+                continue;
+            }
+            _ => continue,
+        };
+        let checksum = package.checksum.clone().or_else(|| {
+            let key = format!("checksum {} {} ({})", package.name, package.version, source);
+            metadata.get(&key).cloned()
+        });
+        packages.insert((package.name, package.version, checksum));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(contents: &str) -> (Vec<PackageId>, BTreeMap<String, usize>) {
+        let mut packages = BTreeSet::new();
+        let mut unsupported = BTreeMap::new();
+        parse_lockfile(contents, &mut packages, &mut unsupported).unwrap();
+        (packages.into_iter().collect(), unsupported)
+    }
+
+    #[test]
+    fn only_crates_io_packages_are_read() {
+        let (packages, unsupported) = parse(
+            r#"
+            version = 3
+
+            [[package]]
+            name = "app"
+            version = "0.1.0"
+
+            [[package]]
+            name = "serde"
+            version = "1.0.0"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+            checksum = "abc"
+
+            [[package]]
+            name = "log"
+            version = "0.4.0"
+            source = "sparse+https://index.crates.io/"
+
+            [[package]]
+            name = "foo"
+            version = "1.0.0"
+            source = "registry+https://my-private-registry.example/index"
+            checksum = "def"
+
+            [[package]]
+            name = "bar"
+            version = "2.0.0"
+            source = "git+https://example.com/bar#0123"
+            "#,
+        );
+        assert_eq!(
+            packages,
+            vec![
+                ("log".to_owned(), "0.4.0".to_owned(), None),
+                (
+                    "serde".to_owned(),
+                    "1.0.0".to_owned(),
+                    Some("abc".to_owned())
+                ),
+            ]
+        );
+        assert_eq!(
+            unsupported.into_iter().collect::<Vec<_>>(),
+            vec![(
+                "registry+https://my-private-registry.example/index".to_owned(),
+                1
+            )]
+        );
+    }
+
+    #[test]
+    fn v1_checksums_are_read_from_metadata() {
+        let (packages, _) = parse(
+            r#"
+            [[package]]
+            name = "serde"
+            version = "1.0.0"
+            source = "registry+https://github.com/rust-lang/crates.io-index"
+
+            [metadata]
+            "checksum serde 1.0.0 (registry+https://github.com/rust-lang/crates.io-index)" = "abc"
+            "#,
+        );
+        assert_eq!(
+            packages,
+            vec![(
+                "serde".to_owned(),
+                "1.0.0".to_owned(),
+                Some("abc".to_owned())
+            )]
+        );
+    }
+}
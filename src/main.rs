@@ -1,134 +1,365 @@
-//! Detect anomalies in the HTTP headers of crate downloads from crates.io.
+//! Command line interface for detecting anomalies in the HTTP headers of crate
+//! downloads from crates.io.
 
-use lazy_static::lazy_static;
-use rayon::prelude::*;
-use reqwest::{header::HeaderMap, Response};
-use serde::Deserialize;
-use walkdir::WalkDir;
+mod options;
+
+use crates_io_header_anomalies::baseline::Baseline;
+use crates_io_header_anomalies::checkpoint::Checkpoint;
+use crates_io_header_anomalies::db::Database;
+use crates_io_header_anomalies::diff::{self, FindingSet};
+use crates_io_header_anomalies::etag::ETagFormat;
+use crates_io_header_anomalies::git;
+use crates_io_header_anomalies::lockfile::{read_lockfiles, CRATES_IO_DL};
+use crates_io_header_anomalies::output::{Reporter, Stats};
+use crates_io_header_anomalies::publish::{read_db_dump, LastModified, PublishTimes};
+use crates_io_header_anomalies::size::{ContentLength, KnownSizes};
+use crates_io_header_anomalies::sparse::{read_crate_list, SparseIndex};
+use crates_io_header_anomalies::{
+    iter_versions, Anomaly, Checks, DownloadTemplate, Fetcher, Outcome, RateLimiter,
+    RegistryConfig, Rules,
+};
+use options::{Options, Target};
+
+use futures::future;
+use futures::stream::{self, StreamExt};
 
 use std::collections::HashSet;
-use std::path::Path;
-use std::sync::atomic::{AtomicU32, Ordering};
+use std::fmt::Display;
+use std::sync::Arc;
 
-fn main() {
-    let index_path = std::env::args().skip(1).next();
-    if index_path.is_none() {
-        println!("Usage: {} <path-to-crates.io-index>", std::env::args().next().unwrap());
+#[tokio::main]
+async fn main() {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            println!("{}", Options::usage(&std::env::args().next().unwrap()));
+            std::process::exit(1);
+        }
+    };
+    if let Some((old, new)) = &options.diff {
+        let reporter = Reporter::new(options.format);
+        let changes = diff::diff(&load_findings(&options, old), &load_findings(&options, new));
+        for change in &changes {
+            reporter.report_change(change);
+        }
+        reporter.diff_summary(&changes);
         return;
     }
-    let index_path = index_path.unwrap();
-    rayon::ThreadPoolBuilder::new().num_threads(100).build_global().unwrap();
-    let client = reqwest::Client::new();
-    let versions: Vec<_> = iter_versions(index_path).collect();
-    let counter = AtomicU32::new(0);
-    versions.par_iter().for_each(|version| {
-        version.get_and_check_headers(&client);
-        counter.fetch_add(1, Ordering::Relaxed);
+    let mut checks = Checks::from(match &options.rules_path {
+        Some(path) => or_exit(Rules::load(path)),
+        None => Rules::default(),
     });
-    println!("Verified {} versions.", counter.into_inner());
-}
-
-#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq)]
-struct Version {
-    name: String,
-    vers: String,
-}
-
-impl Version {
-    fn get_and_check_headers(&self, client: &reqwest::Client) {
-        match self.get_headers(client) {
-            Ok(response) => self.check_headers(response.headers()),
-            Err(e) => println!("{}: {}", self, e),
-        };
-    }
-
-    fn get_headers(&self, client: &reqwest::Client) -> reqwest::Result<Response> {
-        let url = format!(
-            "https://static.crates.io/crates/{crate_name}/{crate_name}-{version}.crate",
-            crate_name = self.name,
-            version = self.vers,
+    checks
+        .add(ContentLength::new(options.min_size, known_sizes(&options)))
+        .add(ETagFormat)
+        .add(LastModified::new(
+            publish_times(&options),
+            options.modified_slack,
+        ));
+    let baseline = match &options.baseline_path {
+        Some(path) => or_exit(Baseline::load(path)),
+        None => Baseline::default(),
+    };
+    for entry in baseline.expired() {
+        eprintln!(
+            "Warning: baseline entry for {} expired on {}{}",
+            entry,
+            entry.expires.unwrap(),
+            entry
+                .reason
+                .as_ref()
+                .map_or(String::new(), |reason| format!(" ({})", reason)),
         );
-        client.head(&url).send()
     }
+    let reporter = Reporter::new(options.format);
+    let stats = match (options.replay, options.target) {
+        (Some(run_id), _) => replay(&options, run_id, &checks, &baseline, &reporter),
+        (None, Target::Crates) => scan(&options, checks, &baseline, &reporter).await,
+        (None, Target::IndexFiles) => scan_index_files(&options, &baseline, &reporter).await,
+    };
+    reporter.summary(&stats);
+}
 
-    fn check_headers(&self, headers: &HeaderMap) {
-        let actual_keys: HashSet<String> = headers
-            .keys()
-            .map(|key| key.as_str().to_lowercase())
-            .collect();
-        lazy_static! {
-            static ref EXPECTED_KEYS: HashSet<String> = [
-                "content-type",
-                "content-length",
-                "connection",
-                "date",
-                "last-modified",
-                "etag",
-                "x-amz-version-id",
-                "accept-ranges",
-                "server",
-                "x-cache",
-                "via",
-                "x-amz-cf-pop",
-                "x-amz-cf-id",
-            ]
-            .iter()
-            .cloned()
-            .map(str::to_owned)
-            .collect();
+/// Check all versions in the index, or all packages in the lock files.
+async fn scan(
+    options: &Options,
+    checks: Checks,
+    baseline: &Baseline,
+    reporter: &Reporter,
+) -> Stats {
+    let fetcher = Arc::new(
+        Fetcher::new(
+            checks,
+            options.download,
+            options.retry,
+            options
+                .rate
+                .map(|rate| RateLimiter::new(rate, options.burst)),
+        )
+        .with_timeout(options.timeout),
+    );
+    let sparse = options.sparse.as_deref().map(SparseIndex::new);
+    let template = match &options.dl {
+        Some(dl) => DownloadTemplate::new(dl),
+        None if !options.lockfiles.is_empty() => DownloadTemplate::new(CRATES_IO_DL),
+        None => {
+            let config = match (&sparse, &options.rev) {
+                (Some(sparse), _) => sparse.config(&fetcher).await,
+                (None, Some(rev)) => git::read_config(&options.index_path, rev),
+                (None, None) => RegistryConfig::load(&options.index_path),
+            };
+            DownloadTemplate::new(&or_exit(config).dl)
         }
-        for key in EXPECTED_KEYS.difference(&actual_keys) {
-            println!("{}: Response did not contain '{}' header.", self, key);
+    };
+    let (mut checkpoint, completed) = match &options.checkpoint {
+        Some(path) => {
+            let (checkpoint, completed) = or_exit(Checkpoint::open(path, options.resume));
+            (Some(checkpoint), completed)
         }
-        for key in actual_keys.difference(&EXPECTED_KEYS) {
-            if key != "age" {
-                println!("{}: Response contained unexpected '{}' header.", self, key);
+        None => (None, HashSet::new()),
+    };
+    let mut db = options.db.as_ref().map(|path| {
+        let mut db = or_exit(Database::open(path));
+        let mut source = match &options.sparse {
+            Some(url) => url.clone(),
+            None if !options.lockfiles.is_empty() => {
+                let paths: Vec<_> = options
+                    .lockfiles
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect();
+                paths.join(", ")
             }
+            None => options.index_path.display().to_string(),
+        };
+        if let Some(rev) = &options.rev {
+            source += &format!(" at {}", rev);
         }
-        self.expect_header(headers, "content-type", "application/x-tar");
-        self.expect_header(headers, "connection", "keep-alive");
-        self.expect_header(headers, "accept-ranges", "bytes");
-        self.expect_header(headers, "server", "AmazonS3");
+        if let Some(since) = &options.since {
+            source += &format!(" since {}", since);
+        }
+        let run_id = or_exit(db.start_run(&source));
+        eprintln!("Recording scan as run {} in {}.", run_id, path.display());
+        db
+    });
+    let index_path = &options.index_path;
+    let rev = options.rev.as_deref();
+    let versions = match (&sparse, &options.since, rev) {
+        _ if !options.lockfiles.is_empty() => {
+            let packages = or_exit(read_lockfiles(&options.lockfiles));
+            for (source, count) in &packages.unsupported {
+                eprintln!(
+                    "Warning: skipped {} package{} from unsupported registry {}",
+                    count,
+                    if *count == 1 { "" } else { "s" },
+                    source
+                );
+            }
+            stream::iter(packages.versions.into_iter().map(Ok)).boxed_local()
+        }
+        (Some(sparse), _, _) => {
+            let mut names = or_exit(read_crate_list(options.crates_path.as_ref().unwrap()));
+            names.retain(|name| options.filter.matches_name(name));
+            let fetcher: &Fetcher = &fetcher;
+            stream::iter(names)
+                .map(move |name| async move { sparse.versions(fetcher, &name).await })
+                .buffer_unordered(options.concurrency)
+                .flat_map(stream::iter)
+                .boxed_local()
+        }
+        (None, Some(since), _) => {
+            stream::iter(or_exit(git::changed_versions(index_path, since, rev))).boxed_local()
+        }
+        (None, None, Some(rev)) => {
+            stream::iter(or_exit(git::iter_versions_at(index_path, rev))).boxed_local()
+        }
+        (None, None, None) => stream::iter(iter_versions(index_path)).boxed_local(),
+    };
+    if let Some(fraction) = options.filter.sample {
+        eprintln!(
+            "Checking a sample of {} of the versions, selected with seed {}.",
+            fraction, options.filter.seed
+        );
     }
+    let mut stats = Stats::default();
+    let mut results = versions
+        .filter(|entry| {
+            let selected = entry
+                .as_ref()
+                .map_or(true, |version| options.filter.matches(version));
+            future::ready(selected)
+        })
+        .map(|entry| async {
+            let version = entry?;
+            if completed.contains(&(version.name.clone(), version.vers.clone())) {
+                return Ok(None);
+            }
+            let url = template.url(&version);
+            // Run each request in its own task, so hashing the body does not
+            // hold up the other requests, and writing the results does not
+            // hold up any of them.
+            let fetcher = Arc::clone(&fetcher);
+            let task = tokio::spawn(async move { fetcher.get_and_check(&version, url).await });
+            Ok(Some(task.await.expect("request task panicked")))
+        })
+        .buffer_unordered(options.concurrency);
+    while let Some(result) = results.next().await {
+        match result {
+            Ok(Some(outcome)) => {
+                reporter.report_outcome(&outcome, baseline, &mut stats);
+                if let Some(checkpoint) = &mut checkpoint {
+                    or_exit(checkpoint.record(&outcome));
+                }
+                if let Some(db) = &mut db {
+                    or_exit(db.record(&outcome));
+                }
+                stats.verified += 1;
+                if outcome.attempts > 1 {
+                    stats.retried += 1;
+                }
+            }
+            Ok(None) => stats.resumed += 1,
+            Err(e) => {
+                reporter.report(&Anomaly::MalformedIndexEntry(e), None);
+                stats.skipped += 1;
+            }
+        }
+    }
+    if let Some(db) = &mut db {
+        or_exit(db.finish());
+    }
+    stats
+}
 
-    fn expect_header(&self, headers: &HeaderMap, key: &str, expected_value: &str) {
-        if let Some(actual_value) = headers.get(key) {
-            if actual_value
-                .to_str()
-                .map(|s| s != expected_value)
-                .unwrap_or(false)
-            {
-                println!(
-                    "{}: Header '{}' has unexpected value '{:?}'.",
-                    self, key, actual_value
-                );
+/// Check the sparse index files of all crates in the crate list.
+async fn scan_index_files(options: &Options, baseline: &Baseline, reporter: &Reporter) -> Stats {
+    let rules = match &options.index_rules_path {
+        Some(path) => or_exit(Rules::load(path)),
+        None => Rules::default_for_index_files(),
+    };
+    let sparse = SparseIndex::new(options.sparse.as_ref().unwrap());
+    let mut names = or_exit(read_crate_list(options.crates_path.as_ref().unwrap()));
+    names.retain(|name| options.filter.matches_name(name));
+    let fetcher = Fetcher::new(
+        rules,
+        options.download,
+        options.retry,
+        options
+            .rate
+            .map(|rate| RateLimiter::new(rate, options.burst)),
+    )
+    .with_timeout(options.timeout);
+    let mut stats = Stats::default();
+    let mut results = stream::iter(names)
+        .map(|name| {
+            let url = sparse.url(&name);
+            let fetcher = &fetcher;
+            async move { fetcher.check_index_file(&name, &url).await }
+        })
+        .buffer_unordered(options.concurrency);
+    while let Some(anomalies) = results.next().await {
+        for anomaly in &anomalies {
+            if baseline.accepts(anomaly) {
+                stats.suppressed += 1;
+            } else {
+                reporter.report(anomaly, None);
             }
         }
+        stats.index_files += 1;
+    }
+    stats
+}
+
+/// Check the responses stored for an earlier run again.
+fn replay(
+    options: &Options,
+    run_id: i64,
+    checks: &Checks,
+    baseline: &Baseline,
+    reporter: &Reporter,
+) -> Stats {
+    let db = or_exit(Database::open(options.db.as_ref().unwrap()));
+    if !or_exit(db.has_run(run_id)) {
+        or_exit(Err(format!("no run with ID {} in the database", run_id)))
+    }
+    let mut stats = Stats::default();
+    for stored in or_exit(db.responses(run_id)) {
+        let outcome = Outcome {
+            anomalies: checks.check_version(
+                &stored.version,
+                &stored.url,
+                stored.observation.status,
+                &stored.observation.headers,
+            ),
+            version: stored.version,
+            url: stored.url,
+            attempts: stored.attempts,
+            observation: Some(stored.observation),
+            size: None,
+        };
+        reporter.report_outcome(&outcome, baseline, &mut stats);
+        stats.verified += 1;
+    }
+    stats
+}
+
+/// Collect the reference sizes of crate files from the crate cache and the
+/// earlier run given in the options.
+fn known_sizes(options: &Options) -> KnownSizes {
+    let mut sizes = KnownSizes::new();
+    if let Some(dir) = &options.crate_cache {
+        sizes = sizes.with_cache_dir(dir);
+    }
+    if let Some(run_id) = options.sizes_from {
+        let db = or_exit(Database::open(options.db.as_ref().unwrap()));
+        if !or_exit(db.has_run(run_id)) {
+            or_exit(Err(format!("no run with ID {} in the database", run_id)))
+        }
+        sizes.extend(or_exit(db.sizes(run_id)));
     }
+    sizes
 }
 
-impl std::fmt::Display for Version {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} ({})", self.name, self.vers)
+/// Collect the publish times of versions from the index history and the
+/// database dump given in the options.
+///
+/// The dump takes precedence, since the index is updated after the crate file
+/// was uploaded.
+fn publish_times(options: &Options) -> PublishTimes {
+    let mut times = PublishTimes::new();
+    if options.publish_history {
+        let rev = options.rev.as_deref().unwrap_or("HEAD");
+        times.extend(or_exit(git::publish_times(&options.index_path, rev)));
     }
+    if let Some(dir) = &options.db_dump {
+        times.extend(or_exit(read_db_dump(dir)));
+    }
+    if options.publish_history || options.db_dump.is_some() {
+        eprintln!("Read the publish times of {} versions.", times.len());
+    }
+    times
 }
 
-fn iter_versions<P: AsRef<Path>>(index_root: P) -> impl Iterator<Item = Version> {
-    use std::io::BufRead;
+/// Load the findings of a run, given as a run ID if a database is used, or
+/// else as the path of a JSON output or checkpoint file.
+fn load_findings(options: &Options, run: &str) -> FindingSet {
+    let path = match &options.db {
+        Some(path) => path,
+        None => return or_exit(diff::load(run)),
+    };
+    let db = or_exit(Database::open(path));
+    let run_id = or_exit(run.parse().map_err(|_| format!("invalid run ID '{}'", run)));
+    if !or_exit(db.has_run(run_id)) {
+        or_exit(Err(format!("no run with ID {} in the database", run_id)))
+    }
+    or_exit(db.findings(run_id))
+}
 
-    WalkDir::new(index_root)
-        .into_iter()
-        .filter_entry(|entry| {
-            let file_name = entry.file_name().to_str().unwrap();
-            file_name != ".git" && file_name != "config.json"
-        })
-        .map(|entry| entry.unwrap())
-        .filter(|entry| entry.file_type().is_file())
-        .flat_map(|entry| {
-            let path = entry.into_path();
-            let file = std::fs::File::open(&path).unwrap();
-            let reader = std::io::BufReader::new(file);
-            reader.lines().map(move |line| line.unwrap())
-        })
-        .map(|line| serde_json::from_str(&line).unwrap())
+/// Unwrap the result, or print the error and exit.
+fn or_exit<T, E: Display>(result: Result<T, E>) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    })
 }
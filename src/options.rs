@@ -0,0 +1,366 @@
+//! Command line parsing.
+
+use crates_io_header_anomalies::fetch::DEFAULT_TIMEOUT;
+use crates_io_header_anomalies::filter::Filter;
+use crates_io_header_anomalies::output::OutputFormat;
+use crates_io_header_anomalies::publish::DEFAULT_SLACK;
+use crates_io_header_anomalies::size::DEFAULT_MIN_SIZE;
+use crates_io_header_anomalies::throttle::MIN_RATE;
+use crates_io_header_anomalies::RetryPolicy;
+
+use glob::Pattern;
+use regex::Regex;
+use semver::VersionReq;
+
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The help text listing all options.
+const HELP: &str = "\
+Options:
+    --format <text|json>      Output format for findings (default: text)
+    --target <crates|index>   Check the crate files, or the sparse index files of the crates
+                              (default: crates)
+    --rules <file>            TOML or JSON file with header rules (default: built-in)
+    --index-rules <file>      TOML or JSON file with header rules for sparse index files
+                              (default: built-in)
+    --baseline <file>         TOML or JSON file listing accepted anomalies, which are not
+                              reported
+    --rev <revision>          Read the index from this git revision instead of the working
+                              tree; the index path may be a bare clone
+    --since <revision>        Only check versions added or changed in the index since this git
+                              revision
+    --sparse <url>            Read the index from a sparse (HTTP) index at this base URL
+                              instead of a local checkout
+    --crates <file>           File listing the names of the crates to read from the sparse
+                              index, one per line
+    --lockfile <file>         Check the crates.io packages in this Cargo.lock instead of the
+                              index, downloading them to verify their checksums; may be
+                              given multiple times
+    --crate <name>            Only check this crate; may be given multiple times
+    --crate-glob <pattern>    Only check crates whose name matches this glob pattern
+    --crate-regex <regex>     Only check crates whose name matches this regular expression
+    --versions <range>        Only check versions matching this semver requirement
+    --no-yanked               Skip yanked versions
+    --sample <fraction>       Only check a random sample of this fraction of the versions
+    --seed <n>                Seed selecting the random sample; required with '--checkpoint'
+                              (default: random)
+    --dl <template>           Download URL template (default: `dl` from the index config.json)
+    --download                Download crate files and verify them against the index checksum
+    --min-size <bytes>        Report crate files with a smaller 'content-length' (default: 100)
+    --crate-cache <dir>       Compare the 'content-length' with the size of the crate files in
+                              this directory, e.g. ~/.cargo/registry/cache/<registry>
+    --sizes-from <run>        Compare the 'content-length' with the size of the crate files
+                              downloaded in this run in the database
+    --publish-history         Compare the 'last-modified' header with the publish time of the
+                              versions, taken from the git history of the index
+    --db-dump <dir>           Compare the 'last-modified' header with the publish time of the
+                              versions in this extracted crates.io database dump
+    --modified-slack <min>    Tolerance in minutes when comparing the 'last-modified' header
+                              with the publish time (default: 60)
+    --retries <n>             Retries for transient request failures (default: 3)
+    --retry-delay <ms>        Delay before the first retry, doubled for every further one
+                              (default: 500)
+    --timeout <s>             Time limit for a request, including the body (default: 30)
+    --concurrency <n>         Maximum number of requests in flight (default: 100)
+    --rate <n>                Maximum number of requests per second (default: unlimited)
+    --burst <n>               Number of requests allowed at once when rate limited (default: 1)
+    --checkpoint <file>       Record completed versions and their findings in this file
+    --resume                  Skip versions already recorded in the checkpoint file
+    --db <file>               Store all responses and findings in this SQLite database
+    --replay <run>            Check the responses stored for a run in the database again,
+                              instead of scanning the index
+
+Commands:
+    diff <old> <new>          Report findings that are new, resolved or changed between two
+                              runs, given as files written with `--format json` or
+                              `--checkpoint`, or as run IDs if `--db` is given
+";
+
+/// What to request and check the headers of.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Target {
+    /// The crate files of all versions.
+    #[default]
+    Crates,
+    /// The sparse index files of all crates.
+    IndexFiles,
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "crates" => Ok(Target::Crates),
+            "index" => Ok(Target::IndexFiles),
+            _ => Err(format!("unknown target '{}'", s)),
+        }
+    }
+}
+
+/// The options the scan was invoked with.
+pub struct Options {
+    pub index_path: PathBuf,
+    pub format: OutputFormat,
+    pub target: Target,
+    pub rules_path: Option<PathBuf>,
+    pub index_rules_path: Option<PathBuf>,
+    pub baseline_path: Option<PathBuf>,
+    pub rev: Option<String>,
+    pub since: Option<String>,
+    pub sparse: Option<String>,
+    pub crates_path: Option<PathBuf>,
+    pub lockfiles: Vec<PathBuf>,
+    pub filter: Filter,
+    pub dl: Option<String>,
+    pub download: bool,
+    pub min_size: u64,
+    pub crate_cache: Option<PathBuf>,
+    pub sizes_from: Option<i64>,
+    pub publish_history: bool,
+    pub db_dump: Option<PathBuf>,
+    pub modified_slack: chrono::Duration,
+    pub retry: RetryPolicy,
+    pub timeout: Duration,
+    pub concurrency: usize,
+    pub rate: Option<f64>,
+    pub burst: u32,
+    pub checkpoint: Option<PathBuf>,
+    pub resume: bool,
+    pub db: Option<PathBuf>,
+    pub replay: Option<i64>,
+    /// The runs to compare, if the `diff` command was given.
+    pub diff: Option<(String, String)>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            index_path: PathBuf::new(),
+            format: OutputFormat::default(),
+            target: Target::default(),
+            rules_path: None,
+            index_rules_path: None,
+            baseline_path: None,
+            rev: None,
+            since: None,
+            sparse: None,
+            crates_path: None,
+            lockfiles: vec![],
+            filter: Filter {
+                seed: rand::random(),
+                ..Filter::default()
+            },
+            dl: None,
+            download: false,
+            min_size: DEFAULT_MIN_SIZE,
+            crate_cache: None,
+            sizes_from: None,
+            publish_history: false,
+            db_dump: None,
+            modified_slack: DEFAULT_SLACK,
+            retry: RetryPolicy::default(),
+            timeout: DEFAULT_TIMEOUT,
+            concurrency: 100,
+            rate: None,
+            burst: 1,
+            checkpoint: None,
+            resume: false,
+            db: None,
+            replay: None,
+            diff: None,
+        }
+    }
+}
+
+impl Options {
+    /// Parse the options from the given command line arguments, excluding the
+    /// program name.
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Self, String> {
+        let mut options = Options::default();
+        let mut positional = vec![];
+        let mut seed_given = false;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--format" => options.format = value(&mut args, &arg)?.parse()?,
+                "--target" => options.target = value(&mut args, &arg)?.parse()?,
+                "--rules" => options.rules_path = Some(value(&mut args, &arg)?.into()),
+                "--index-rules" => options.index_rules_path = Some(value(&mut args, &arg)?.into()),
+                "--baseline" => options.baseline_path = Some(value(&mut args, &arg)?.into()),
+                "--rev" => options.rev = Some(value(&mut args, &arg)?),
+                "--since" => options.since = Some(value(&mut args, &arg)?),
+                "--sparse" => options.sparse = Some(value(&mut args, &arg)?),
+                "--crates" => options.crates_path = Some(value(&mut args, &arg)?.into()),
+                "--lockfile" => options.lockfiles.push(value(&mut args, &arg)?.into()),
+                "--crate" => {
+                    options.filter.names.insert(value(&mut args, &arg)?);
+                }
+                "--crate-glob" => {
+                    let pattern = value(&mut args, &arg)?;
+                    options.filter.glob = Some(
+                        Pattern::new(&pattern)
+                            .map_err(|e| format!("invalid glob pattern '{}': {}", pattern, e))?,
+                    );
+                }
+                "--crate-regex" => {
+                    let regex = value(&mut args, &arg)?;
+                    options.filter.regex =
+                        Some(Regex::new(&regex).map_err(|e| {
+                            format!("invalid regular expression '{}': {}", regex, e)
+                        })?);
+                }
+                "--versions" => {
+                    let req = value(&mut args, &arg)?;
+                    options.filter.versions =
+                        Some(VersionReq::parse(&req).map_err(|e| {
+                            format!("invalid version requirement '{}': {}", req, e)
+                        })?);
+                }
+                "--no-yanked" => options.filter.skip_yanked = true,
+                "--sample" => options.filter.sample = Some(number(&mut args, &arg)?),
+                "--seed" => {
+                    options.filter.seed = number(&mut args, &arg)?;
+                    seed_given = true;
+                }
+                "--dl" => options.dl = Some(value(&mut args, &arg)?),
+                "--download" => options.download = true,
+                "--min-size" => options.min_size = number(&mut args, &arg)?,
+                "--crate-cache" => options.crate_cache = Some(value(&mut args, &arg)?.into()),
+                "--sizes-from" => options.sizes_from = Some(number(&mut args, &arg)?),
+                "--publish-history" => options.publish_history = true,
+                "--db-dump" => options.db_dump = Some(value(&mut args, &arg)?.into()),
+                "--modified-slack" => {
+                    let minutes: u32 = number(&mut args, &arg)?;
+                    options.modified_slack = chrono::Duration::minutes(minutes.into())
+                }
+                "--retries" => options.retry.retries = number(&mut args, &arg)?,
+                "--retry-delay" => {
+                    options.retry.base_delay = Duration::from_millis(number(&mut args, &arg)?)
+                }
+                "--timeout" => options.timeout = Duration::from_secs(number(&mut args, &arg)?),
+                "--concurrency" => options.concurrency = number(&mut args, &arg)?,
+                "--rate" => options.rate = Some(number(&mut args, &arg)?),
+                "--burst" => options.burst = number(&mut args, &arg)?,
+                "--checkpoint" => options.checkpoint = Some(value(&mut args, &arg)?.into()),
+                "--resume" => options.resume = true,
+                "--db" => options.db = Some(value(&mut args, &arg)?.into()),
+                "--replay" => options.replay = Some(number(&mut args, &arg)?),
+                _ if arg.starts_with('-') => return Err(format!("unknown option '{}'", arg)),
+                _ => positional.push(arg),
+            }
+        }
+        let mut positional = positional.into_iter().peekable();
+        if positional.next_if(|arg| arg == "diff").is_some() {
+            let old = positional
+                .next()
+                .ok_or("'diff' requires two runs to compare")?;
+            let new = positional
+                .next()
+                .ok_or("'diff' requires two runs to compare")?;
+            options.diff = Some((old, new));
+        } else if options.replay.is_some() {
+            if options.db.is_none() {
+                return Err("'--replay' requires '--db'".to_owned());
+            }
+        } else if !options.lockfiles.is_empty() {
+            if options.sparse.is_some() || options.rev.is_some() || options.since.is_some() {
+                return Err("'--lockfile' cannot be combined with an index".to_owned());
+            }
+            options.download = true;
+        } else if options.sparse.is_some() {
+            if options.crates_path.is_none() {
+                return Err("'--sparse' requires '--crates'".to_owned());
+            }
+            if options.rev.is_some() || options.since.is_some() {
+                return Err("'--rev' and '--since' require a git index".to_owned());
+            }
+        } else {
+            options.index_path = positional.next().ok_or("missing path to the index")?.into();
+        }
+        if let Some(arg) = positional.next() {
+            return Err(format!("unexpected argument '{}'", arg));
+        }
+        if options.target == Target::IndexFiles {
+            if options.sparse.is_none() {
+                return Err("'--target index' requires '--sparse'".to_owned());
+            }
+            if options.checkpoint.is_some() || options.db.is_some() {
+                return Err(
+                    "'--target index' cannot be combined with '--checkpoint' or '--db'".to_owned(),
+                );
+            }
+        }
+        if options.publish_history && options.index_path.as_os_str().is_empty() {
+            return Err("'--publish-history' requires a git index".to_owned());
+        }
+        if options.sizes_from.is_some() && options.db.is_none() {
+            return Err("'--sizes-from' requires '--db'".to_owned());
+        }
+        if options.resume && options.checkpoint.is_none() {
+            return Err("'--resume' requires '--checkpoint'".to_owned());
+        }
+        // The responses of versions skipped when resuming are not known, so
+        // the run in the database would be incomplete.
+        if options.resume && options.db.is_some() {
+            return Err("'--resume' cannot be combined with '--db'".to_owned());
+        }
+        if options
+            .filter
+            .sample
+            .is_some_and(|fraction| !(fraction > 0.0 && fraction <= 1.0))
+        {
+            return Err("the sample fraction must be greater than 0 and at most 1".to_owned());
+        }
+        // A random seed would select a different sample when resuming.
+        if options.filter.sample.is_some() && options.checkpoint.is_some() && !seed_given {
+            return Err("'--sample' with '--checkpoint' requires '--seed'".to_owned());
+        }
+        if options.timeout.is_zero() {
+            return Err("the timeout must be at least 1 second".to_owned());
+        }
+        if options.concurrency == 0 {
+            return Err("the concurrency must be at least 1".to_owned());
+        }
+        if options
+            .rate
+            .is_some_and(|rate| !rate.is_finite() || rate < MIN_RATE)
+        {
+            return Err(format!(
+                "the rate must be a finite number of at least {}",
+                MIN_RATE
+            ));
+        }
+        Ok(options)
+    }
+
+    pub fn usage(program: &str) -> String {
+        format!(
+            "Usage: {0} [OPTIONS] <path-to-crates.io-index>\n       \
+             {0} --sparse <url> --crates <file> [OPTIONS]\n       \
+             {0} --lockfile <file> [OPTIONS]\n       \
+             {0} --db <file> --replay <run> [OPTIONS]\n       \
+             {0} [--db <file>] [--format <text|json>] diff <old> <new>\n\n{1}",
+            program, HELP
+        )
+    }
+}
+
+/// Take the value for the option `flag` from the argument list.
+fn value<I: Iterator<Item = String>>(args: &mut I, flag: &str) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("option '{}' requires a value", flag))
+}
+
+/// Take the numeric value for the option `flag` from the argument list.
+fn number<I, T>(args: &mut I, flag: &str) -> Result<T, String>
+where
+    I: Iterator<Item = String>,
+    T: std::str::FromStr,
+{
+    let value = value(args, flag)?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{}' for option '{}'", value, flag))
+}
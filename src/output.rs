@@ -0,0 +1,196 @@
+//! Reporting of anomalies in human-readable or machine-readable form.
+
+use crate::anomaly::Anomaly;
+use crate::baseline::Baseline;
+use crate::diff::{Change, ChangeKind};
+use crate::fetch::{ErrorClass, Outcome};
+use crate::index::Version;
+
+use serde::Serialize;
+
+use std::str::FromStr;
+
+/// The format findings are written to standard output in.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum OutputFormat {
+    /// One human-readable line per anomaly.
+    #[default]
+    Text,
+    /// One JSON object per line (NDJSON).
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" | "ndjson" => Ok(OutputFormat::Json),
+            _ => Err(format!("unknown output format '{}'", s)),
+        }
+    }
+}
+
+/// The JSON representation of a single finding.
+#[derive(Serialize)]
+struct Record<'a> {
+    #[serde(rename = "crate")]
+    crate_name: Option<&'a str>,
+    version: Option<&'a str>,
+    url: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    yanked: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cksum: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    schema_version: Option<u32>,
+    kind: &'a str,
+    header: Option<&'a str>,
+    expected: Option<String>,
+    actual: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_class: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attempts: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line: Option<usize>,
+    timestamp: String,
+}
+
+/// Counters for the summary at the end of a scan.
+#[derive(Debug, Default)]
+pub struct Stats {
+    /// The number of versions checked.
+    pub verified: u32,
+    /// The number of sparse index files checked.
+    pub index_files: u32,
+    /// The number of index entries that could not be read or parsed.
+    pub skipped: u32,
+    /// The number of versions that needed more than one attempt.
+    pub retried: u32,
+    /// The number of versions skipped because they were completed in an
+    /// earlier run.
+    pub resumed: u32,
+    /// The number of anomalies not reported because they are accepted in the
+    /// baseline.
+    pub suppressed: u32,
+}
+
+/// Writes findings to standard output in the selected format.
+pub struct Reporter {
+    format: OutputFormat,
+}
+
+impl Reporter {
+    /// Create a reporter writing in the given format.
+    pub fn new(format: OutputFormat) -> Self {
+        Reporter { format }
+    }
+
+    /// Report all anomalies found when checking a version, except for those
+    /// accepted in the baseline, and count them in the stats.
+    pub fn report_outcome(&self, outcome: &Outcome, baseline: &Baseline, stats: &mut Stats) {
+        for anomaly in &outcome.anomalies {
+            if baseline.accepts(anomaly) {
+                stats.suppressed += 1;
+            } else {
+                self.report(anomaly, Some(outcome));
+            }
+        }
+    }
+
+    /// Report an anomaly, with the outcome of the check it was found in, if any.
+    pub fn report(&self, anomaly: &Anomaly, outcome: Option<&Outcome>) {
+        match self.format {
+            OutputFormat::Text => println!("{}", anomaly),
+            OutputFormat::Json => {
+                let version = anomaly.version();
+                let index_error = match anomaly {
+                    Anomaly::MalformedIndexEntry(e) => Some(e),
+                    _ => None,
+                };
+                let record = Record {
+                    crate_name: anomaly.crate_name(),
+                    version: version.map(|v| v.vers.as_str()),
+                    url: match anomaly {
+                        Anomaly::IndexFile { url, .. } => Some(url),
+                        _ => outcome.map(|o| o.url.as_str()),
+                    },
+                    yanked: version.map(|v| v.yanked),
+                    cksum: version.and_then(|v| v.cksum.as_deref()),
+                    schema_version: version.map(Version::schema_version),
+                    kind: anomaly.kind(),
+                    header: anomaly.header(),
+                    expected: anomaly.expected(),
+                    actual: anomaly.actual(),
+                    error_class: anomaly.error_class().map(ErrorClass::as_str),
+                    attempts: outcome.map(|o| o.attempts),
+                    file: index_error.map(|e| e.path.display().to_string()),
+                    line: index_error.and_then(|e| e.line),
+                    timestamp: chrono::Utc::now().to_rfc3339(),
+                };
+                println!("{}", serde_json::to_string(&record).unwrap());
+            }
+        }
+    }
+
+    /// Report the final summary of a scan.
+    ///
+    /// In JSON mode the summary goes to standard error, so standard output only
+    /// contains findings.
+    pub fn summary(&self, stats: &Stats) {
+        let mut summary = if stats.index_files > 0 && stats.verified == 0 {
+            format!("Checked {} index files.", stats.index_files)
+        } else {
+            format!("Verified {} versions.", stats.verified)
+        };
+        if stats.retried > 0 {
+            summary += &format!(" {} needed retries.", stats.retried);
+        }
+        if stats.resumed > 0 {
+            summary += &format!(
+                " Skipped {} versions verified in an earlier run.",
+                stats.resumed
+            );
+        }
+        if stats.skipped > 0 {
+            summary += &format!(" Skipped {} malformed index entries.", stats.skipped);
+        }
+        if stats.suppressed > 0 {
+            summary += &format!(
+                " Suppressed {} anomalies accepted in the baseline.",
+                stats.suppressed
+            );
+        }
+        match self.format {
+            OutputFormat::Text => println!("{}", summary),
+            OutputFormat::Json => eprintln!("{}", summary),
+        }
+    }
+
+    /// Report a difference between two runs.
+    pub fn report_change(&self, change: &Change) {
+        match self.format {
+            OutputFormat::Text => println!("{}", change),
+            OutputFormat::Json => println!("{}", serde_json::to_string(change).unwrap()),
+        }
+    }
+
+    /// Report the number of differences between two runs.
+    pub fn diff_summary(&self, changes: &[Change]) {
+        let count = |kind| changes.iter().filter(|c| c.change == kind).count();
+        let summary = format!(
+            "{} new, {} resolved, {} changed findings.",
+            count(ChangeKind::New),
+            count(ChangeKind::Resolved),
+            count(ChangeKind::Changed)
+        );
+        match self.format {
+            OutputFormat::Text => println!("{}", summary),
+            OutputFormat::Json => eprintln!("{}", summary),
+        }
+    }
+}
@@ -0,0 +1,285 @@
+//! Comparing the `last-modified` header of crate files with the time the
+//! versions were published.
+
+use crate::anomaly::Issue;
+use crate::check::{CheckedResponse, HeaderCheck};
+
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use reqwest::header::LAST_MODIFIED;
+use serde::Deserialize;
+
+use std::collections::HashMap;
+use std::path::Path;
+
+/// The publish times of versions, keyed by crate name and version number.
+pub type PublishTimes = HashMap<(String, String), DateTime<Utc>>;
+
+/// The default tolerance when comparing the `last-modified` header with the
+/// publish time.
+///
+/// The crate file is uploaded before the version is added to the index, and
+/// the index may lag behind by a few minutes.
+pub const DEFAULT_SLACK: Duration = Duration::hours(1);
+
+#[derive(Deserialize)]
+struct CrateRow {
+    id: u64,
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct VersionRow {
+    crate_id: u64,
+    num: String,
+    created_at: String,
+}
+
+/// Read the publish times from an extracted crates.io database dump, i.e. the
+/// directory containing the `data` directory with `crates.csv` and
+/// `versions.csv`.
+pub fn read_db_dump<P: AsRef<Path>>(dir: P) -> Result<PublishTimes, String> {
+    let data = dir.as_ref().join("data");
+    let mut names = HashMap::new();
+    for row in open_csv(&data.join("crates.csv"))?.deserialize() {
+        let row: CrateRow = row.map_err(|e| describe(&data.join("crates.csv"), e))?;
+        names.insert(row.id, row.name);
+    }
+    let path = data.join("versions.csv");
+    let mut times = PublishTimes::new();
+    for row in open_csv(&path)?.deserialize() {
+        let row: VersionRow = row.map_err(|e| describe(&path, e))?;
+        let created_at = parse_timestamp(&row.created_at).ok_or_else(|| {
+            format!(
+                "invalid timestamp '{}' in {}",
+                row.created_at,
+                path.display()
+            )
+        })?;
+        if let Some(name) = names.get(&row.crate_id) {
+            times.insert((name.clone(), row.num), created_at);
+        }
+    }
+    Ok(times)
+}
+
+fn open_csv(path: &Path) -> Result<csv::Reader<std::fs::File>, String> {
+    csv::Reader::from_path(path).map_err(|e| describe(path, e))
+}
+
+fn describe(path: &Path, e: csv::Error) -> String {
+    format!("cannot read database dump {}: {}", path.display(), e)
+}
+
+/// Parse a timestamp as written by PostgreSQL, which is in UTC in the dump.
+fn parse_timestamp(s: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f")
+        .map(|t| t.and_utc())
+        .or_else(|_| DateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f%#z").map(|t| t.into()))
+        .ok()
+}
+
+/// Reports successful responses for crate files whose `last-modified` header
+/// is not a valid HTTP date, or differs from the publish time of the version
+/// by more than the slack.
+///
+/// A crate file modified long after publication may have been overwritten,
+/// and one modified before publication has an implausible date.  Versions
+/// with an unknown publish time are only checked for a valid date.
+pub struct LastModified {
+    times: PublishTimes,
+    slack: Duration,
+}
+
+impl LastModified {
+    /// Compare the `last-modified` header with the given publish times.
+    pub fn new(times: PublishTimes, slack: Duration) -> Self {
+        LastModified { times, slack }
+    }
+}
+
+impl HeaderCheck for LastModified {
+    fn check(&self, response: &CheckedResponse<'_>) -> Vec<Issue> {
+        let version = match response.version {
+            Some(version) if response.status == 200 => version,
+            _ => return vec![],
+        };
+        let value = match response.headers.get(LAST_MODIFIED) {
+            Some(value) => String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            None => return vec![],
+        };
+        let modified: DateTime<Utc> = match httpdate::parse_http_date(&value) {
+            Ok(modified) => modified.into(),
+            Err(_) => {
+                return vec![issue(
+                    "invalid_last_modified",
+                    format!("Header 'last-modified' is not an HTTP date: {}.", value),
+                )]
+            }
+        };
+        let published = match self
+            .times
+            .get(&(version.name.clone(), version.vers.clone()))
+        {
+            Some(published) => *published,
+            None => return vec![],
+        };
+        // The bounds are out of range for times near the limits of `DateTime`,
+        // in which case nothing can be beyond them.
+        let after = published
+            .checked_add_signed(self.slack)
+            .is_some_and(|latest| modified > latest);
+        let before = published
+            .checked_sub_signed(self.slack)
+            .is_some_and(|earliest| modified < earliest);
+        if after {
+            vec![issue(
+                "modified_after_publication",
+                format!(
+                    "Header 'last-modified' is {}, {} after the version was published at {}.",
+                    value,
+                    describe_duration(modified - published),
+                    published.to_rfc3339()
+                ),
+            )]
+        } else if before {
+            vec![issue(
+                "modified_before_publication",
+                format!(
+                    "Header 'last-modified' is {}, {} before the version was published at {}.",
+                    value,
+                    describe_duration(published - modified),
+                    published.to_rfc3339()
+                ),
+            )]
+        } else {
+            vec![]
+        }
+    }
+}
+
+/// Describe a duration in days, hours or minutes, whichever fits best.
+fn describe_duration(duration: Duration) -> String {
+    if duration.num_days() >= 2 {
+        format!("{} days", duration.num_days())
+    } else if duration.num_hours() >= 2 {
+        format!("{} hours", duration.num_hours())
+    } else {
+        format!("{} minutes", duration.num_minutes())
+    }
+}
+
+fn issue(kind: &str, message: String) -> Issue {
+    Issue::Custom {
+        kind: kind.to_owned(),
+        header: Some("last-modified".to_owned()),
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::index::Version;
+
+    use chrono::TimeZone;
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    fn check(slack: Duration, published: DateTime<Utc>, last_modified: &str) -> Vec<String> {
+        let version = Version {
+            name: "serde".to_owned(),
+            vers: "1.0.0".to_owned(),
+            ..Version::default()
+        };
+        let times = vec![(("serde".to_owned(), "1.0.0".to_owned()), published)];
+        let mut headers = HeaderMap::new();
+        headers.insert(LAST_MODIFIED, HeaderValue::from_str(last_modified).unwrap());
+        let response = CheckedResponse {
+            version: Some(&version),
+            url: "https://example.com/serde/serde-1.0.0.crate",
+            status: 200,
+            headers: &headers,
+        };
+        LastModified::new(times.into_iter().collect(), slack)
+            .check(&response)
+            .into_iter()
+            .map(|issue| match issue {
+                Issue::Custom { kind, .. } => kind,
+                issue => panic!("unexpected issue {:?}", issue),
+            })
+            .collect()
+    }
+
+    fn published() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2020, 6, 1, 12, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn modified_within_slack() {
+        assert!(check(DEFAULT_SLACK, published(), "Mon, 01 Jun 2020 12:00:00 GMT").is_empty());
+        assert!(check(DEFAULT_SLACK, published(), "Mon, 01 Jun 2020 12:59:00 GMT").is_empty());
+        assert!(check(DEFAULT_SLACK, published(), "Mon, 01 Jun 2020 11:01:00 GMT").is_empty());
+    }
+
+    #[test]
+    fn modified_after_publication() {
+        assert_eq!(
+            check(DEFAULT_SLACK, published(), "Mon, 01 Jun 2020 13:01:00 GMT"),
+            ["modified_after_publication"]
+        );
+        assert_eq!(
+            check(
+                Duration::zero(),
+                published(),
+                "Mon, 01 Jun 2020 12:01:00 GMT"
+            ),
+            ["modified_after_publication"]
+        );
+    }
+
+    #[test]
+    fn modified_before_publication() {
+        assert_eq!(
+            check(DEFAULT_SLACK, published(), "Mon, 01 Jun 2020 10:59:00 GMT"),
+            ["modified_before_publication"]
+        );
+    }
+
+    #[test]
+    fn slack_beyond_the_range_of_dates() {
+        let slack = Duration::minutes(u32::MAX.into());
+        let modified = "Mon, 01 Jun 2020 12:00:00 GMT";
+        assert_eq!(
+            check(slack, DateTime::<Utc>::MAX_UTC, modified),
+            ["modified_before_publication"]
+        );
+        assert_eq!(
+            check(slack, DateTime::<Utc>::MIN_UTC, modified),
+            ["modified_after_publication"]
+        );
+    }
+
+    #[test]
+    fn invalid_last_modified() {
+        assert_eq!(
+            check(DEFAULT_SLACK, published(), "2020-06-01 12:00:00"),
+            ["invalid_last_modified"]
+        );
+    }
+
+    #[test]
+    fn timestamps_with_and_without_offset() {
+        let expected = Utc.with_ymd_and_hms(2020, 6, 1, 12, 0, 0).unwrap();
+        assert_eq!(parse_timestamp("2020-06-01 12:00:00"), Some(expected));
+        assert_eq!(
+            parse_timestamp("2020-06-01 12:00:00.25"),
+            Some(expected + Duration::milliseconds(250))
+        );
+        assert_eq!(parse_timestamp("2020-06-01 12:00:00+00"), Some(expected));
+        assert_eq!(
+            parse_timestamp("2020-06-01 14:00:00.5+02"),
+            Some(expected + Duration::milliseconds(500))
+        );
+        assert_eq!(parse_timestamp("2020-06-01T12:00:00Z"), None);
+    }
+}
@@ -0,0 +1,124 @@
+//! Declarative rules describing the expected headers of a response.
+
+use crate::check::{Checks, Expectation, ExpectedValue, KnownHeaders, RequiredHeaders};
+
+use regex::Regex;
+use serde::Deserialize;
+
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// The rules used when no rules file is given on the command line.
+const DEFAULT_RULES: &str = include_str!("default_rules.toml");
+
+/// The rules for sparse index files used when no index rules file is given.
+const DEFAULT_INDEX_RULES: &str = include_str!("default_index_rules.toml");
+
+/// The on-disk representation of a rules file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    #[serde(default)]
+    required: Vec<String>,
+    #[serde(default)]
+    optional: Vec<String>,
+    #[serde(default, rename = "value")]
+    values: Vec<ValueRuleFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ValueRuleFile {
+    header: String,
+    equals: Option<String>,
+    matches: Option<String>,
+}
+
+/// The set of expectations a response is checked against.
+///
+/// The rules are turned into the built-in [`HeaderCheck`](crate::check::HeaderCheck)
+/// implementations by converting them into [`Checks`].
+pub struct Rules {
+    required: BTreeSet<String>,
+    optional: BTreeSet<String>,
+    values: Vec<ExpectedValue>,
+}
+
+impl Rules {
+    /// Load the rules from a TOML file, or from a JSON file if the file name
+    /// ends in `.json`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read rules file {}: {}", path.display(), e))?;
+        let file = if path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::from_str(&contents).map_err(|e| e.to_string())
+        } else {
+            toml::from_str(&contents).map_err(|e| e.to_string())
+        };
+        file.and_then(Self::from_file)
+            .map_err(|e| format!("invalid rules file {}: {}", path.display(), e))
+    }
+
+    fn from_file(file: RulesFile) -> Result<Self, String> {
+        let values = file
+            .values
+            .into_iter()
+            .map(|rule| {
+                let expectation = match (rule.equals, rule.matches) {
+                    (Some(value), None) => Expectation::Equals(value),
+                    (None, Some(pattern)) => {
+                        Expectation::Matches(Regex::new(&pattern).map_err(|e| e.to_string())?)
+                    }
+                    _ => {
+                        return Err(format!(
+                            "value rule for '{}' needs exactly one of 'equals' and 'matches'",
+                            rule.header
+                        ))
+                    }
+                };
+                Ok(ExpectedValue::new(&rule.header, expectation))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Rules {
+            required: file.required.iter().map(|h| h.to_lowercase()).collect(),
+            optional: file.optional.iter().map(|h| h.to_lowercase()).collect(),
+            values,
+        })
+    }
+}
+
+impl From<Rules> for Checks {
+    /// The checks for missing headers, unexpected headers and header values
+    /// described by the rules, in this order.
+    fn from(rules: Rules) -> Self {
+        let mut checks = Checks::new();
+        checks
+            .add(RequiredHeaders::new(&rules.required))
+            .add(KnownHeaders::new(rules.required.union(&rules.optional)));
+        for value in rules.values {
+            checks.add(value);
+        }
+        checks
+    }
+}
+
+impl Rules {
+    /// The built-in rules for sparse index files.
+    pub fn default_for_index_files() -> Self {
+        Self::builtin(DEFAULT_INDEX_RULES)
+    }
+
+    fn builtin(contents: &str) -> Self {
+        toml::from_str(contents)
+            .map_err(|e| e.to_string())
+            .and_then(Self::from_file)
+            .expect("built-in rules are valid")
+    }
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self::builtin(DEFAULT_RULES)
+    }
+}
@@ -0,0 +1,236 @@
+//! Checking the `content-length` of crate files against their actual size.
+
+use crate::anomaly::Issue;
+use crate::check::{CheckedResponse, HeaderCheck};
+use crate::index::Version;
+
+use reqwest::header::CONTENT_LENGTH;
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// The default size below which a crate file is considered suspiciously
+/// small.
+///
+/// Even a gzipped tarball holding nothing but a short manifest is larger than
+/// this.
+pub const DEFAULT_MIN_SIZE: u64 = 100;
+
+/// The known sizes of crate files, used as the reference for the
+/// `content-length` header.
+#[derive(Default)]
+pub struct KnownSizes {
+    sizes: HashMap<(String, String), u64>,
+    cache_dir: Option<PathBuf>,
+}
+
+impl KnownSizes {
+    /// Create an empty set of sizes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Look up sizes that are not known otherwise in a directory of crate
+    /// files named `<name>-<version>.crate`, like Cargo's download cache in
+    /// `~/.cargo/registry/cache/<registry>`.
+    pub fn with_cache_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.cache_dir = Some(dir.into());
+        self
+    }
+
+    /// Add the sizes of crate files, keyed by crate name and version number,
+    /// e.g. the sizes recorded in an earlier run.
+    pub fn extend<I: IntoIterator<Item = ((String, String), u64)>>(&mut self, sizes: I) {
+        self.sizes.extend(sizes);
+    }
+
+    /// The size of the crate file of `version`, if known.
+    pub fn get(&self, version: &Version) -> Option<u64> {
+        if let Some(&size) = self
+            .sizes
+            .get(&(version.name.clone(), version.vers.clone()))
+        {
+            return Some(size);
+        }
+        let dir = self.cache_dir.as_ref()?;
+        let path = dir.join(format!("{}-{}.crate", version.name, version.vers));
+        std::fs::metadata(path).ok().map(|metadata| metadata.len())
+    }
+}
+
+/// Reports successful responses for crate files whose `content-length` is
+/// zero, suspiciously small, or differs from the known size of the file.
+///
+/// These usually indicate broken uploads or truncation by the CDN.  Responses
+/// without a `content-length` header and responses for other files are not
+/// checked.  When downloading, the length of the body received is checked
+/// separately.
+pub struct ContentLength {
+    min_size: u64,
+    known: KnownSizes,
+}
+
+impl ContentLength {
+    /// Check the `content-length` against `min_size` and the known sizes.
+    pub fn new(min_size: u64, known: KnownSizes) -> Self {
+        ContentLength { min_size, known }
+    }
+}
+
+impl HeaderCheck for ContentLength {
+    fn check(&self, response: &CheckedResponse<'_>) -> Vec<Issue> {
+        let version = match response.version {
+            Some(version) if response.status == 200 => version,
+            _ => return vec![],
+        };
+        let value = match response.headers.get(CONTENT_LENGTH) {
+            Some(value) => value,
+            None => return vec![],
+        };
+        let content_length = match value.to_str().ok().and_then(|v| v.parse::<u64>().ok()) {
+            Some(content_length) => content_length,
+            None => {
+                return vec![issue(
+                    "invalid_content_length",
+                    format!("Header 'content-length' is not a number: {:?}.", value),
+                )]
+            }
+        };
+        let mut issues = vec![];
+        if content_length == 0 {
+            issues.push(issue(
+                "empty_crate_file",
+                "Header 'content-length' is 0.".to_owned(),
+            ));
+        } else if content_length < self.min_size {
+            issues.push(issue(
+                "small_crate_file",
+                format!(
+                    "Header 'content-length' is {}, less than {} bytes.",
+                    content_length, self.min_size
+                ),
+            ));
+        }
+        if let Some(size) = self.known.get(version) {
+            if size != content_length {
+                issues.push(issue(
+                    "crate_size_mismatch",
+                    format!(
+                        "Header 'content-length' is {}, but the crate file has {} bytes.",
+                        content_length, size
+                    ),
+                ));
+            }
+        }
+        issues
+    }
+}
+
+fn issue(kind: &str, message: String) -> Issue {
+    Issue::Custom {
+        kind: kind.to_owned(),
+        header: Some("content-length".to_owned()),
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    fn version() -> Version {
+        Version {
+            name: "serde".to_owned(),
+            vers: "1.0.0".to_owned(),
+            ..Version::default()
+        }
+    }
+
+    fn known(size: u64) -> KnownSizes {
+        let mut known = KnownSizes::new();
+        known.extend(vec![(("serde".to_owned(), "1.0.0".to_owned()), size)]);
+        known
+    }
+
+    fn check_response(
+        known: KnownSizes,
+        version: Option<&Version>,
+        status: u16,
+        value: &[u8],
+    ) -> Vec<String> {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_LENGTH, HeaderValue::from_bytes(value).unwrap());
+        let response = CheckedResponse {
+            version,
+            url: "https://example.com/serde/serde-1.0.0.crate",
+            status,
+            headers: &headers,
+        };
+        ContentLength::new(DEFAULT_MIN_SIZE, known)
+            .check(&response)
+            .into_iter()
+            .map(|issue| match issue {
+                Issue::Custom { kind, .. } => kind,
+                issue => panic!("unexpected issue {:?}", issue),
+            })
+            .collect()
+    }
+
+    fn check(known: KnownSizes, value: &[u8]) -> Vec<String> {
+        check_response(known, Some(&version()), 200, value)
+    }
+
+    #[test]
+    fn plausible_size() {
+        assert!(check(KnownSizes::new(), b"12345").is_empty());
+        assert!(check(known(12345), b"12345").is_empty());
+    }
+
+    #[test]
+    fn empty_and_small_files() {
+        assert_eq!(check(KnownSizes::new(), b"0"), ["empty_crate_file"]);
+        assert_eq!(check(KnownSizes::new(), b"99"), ["small_crate_file"]);
+        assert!(check(KnownSizes::new(), b"100").is_empty());
+    }
+
+    #[test]
+    fn size_mismatch() {
+        assert_eq!(check(known(12345), b"12344"), ["crate_size_mismatch"]);
+        assert_eq!(
+            check(known(12345), b"0"),
+            ["empty_crate_file", "crate_size_mismatch"]
+        );
+    }
+
+    #[test]
+    fn invalid_content_length() {
+        assert_eq!(check(known(12345), b"abc"), ["invalid_content_length"]);
+        assert_eq!(check(known(12345), b"-1"), ["invalid_content_length"]);
+        assert_eq!(check(known(12345), b"1\xff"), ["invalid_content_length"]);
+    }
+
+    #[test]
+    fn other_responses_are_ignored() {
+        assert!(check_response(known(12345), Some(&version()), 404, b"0").is_empty());
+        assert!(check_response(known(12345), None, 200, b"0").is_empty());
+    }
+
+    #[test]
+    fn sizes_are_looked_up_in_cache_dir() {
+        let dir = std::env::temp_dir().join(format!("crate-cache-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("serde-1.0.0.crate"), [0; 150]).unwrap();
+        let cached = KnownSizes::new().with_cache_dir(&dir).get(&version());
+        let other = KnownSizes::new().with_cache_dir(&dir).get(&Version {
+            vers: "1.0.1".to_owned(),
+            ..version()
+        });
+        let preferred = known(12345).with_cache_dir(&dir).get(&version());
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(cached, Some(150));
+        assert_eq!(other, None);
+        assert_eq!(preferred, Some(12345));
+    }
+}
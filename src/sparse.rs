@@ -0,0 +1,76 @@
+//! Reading crate versions from a sparse (HTTP) registry index.
+
+use crate::config::{prefix, RegistryConfig};
+use crate::fetch::Fetcher;
+use crate::index::{parse_versions, IndexError, Version};
+
+use std::path::{Path, PathBuf};
+
+/// A registry index served over HTTP using Cargo's sparse protocol.
+///
+/// The files are requested with a [`Fetcher`], so the requests share its rate
+/// limiter, retry policy and timeout with all other requests.
+pub struct SparseIndex {
+    base_url: String,
+}
+
+impl SparseIndex {
+    /// Create a sparse index rooted at `base_url`, e.g.
+    /// `https://index.crates.io/`.  A `sparse+` prefix is ignored.
+    pub fn new(base_url: &str) -> Self {
+        let base_url = base_url.strip_prefix("sparse+").unwrap_or(base_url);
+        SparseIndex {
+            base_url: base_url.trim_end_matches('/').to_owned(),
+        }
+    }
+
+    /// The URL of the index file for a crate.
+    pub fn url(&self, name: &str) -> String {
+        let name = name.to_lowercase();
+        format!("{}/{}/{}", self.base_url, prefix(&name), name)
+    }
+
+    /// Fetch the registry configuration from `config.json`.
+    pub async fn config(&self, fetcher: &Fetcher) -> Result<RegistryConfig, String> {
+        let url = format!("{}/config.json", self.base_url);
+        let contents = fetcher
+            .fetch(&url)
+            .await
+            .map_err(|e| format!("cannot fetch {}: {}", url, e))?;
+        RegistryConfig::parse(&String::from_utf8_lossy(&contents), &url)
+    }
+
+    /// Fetch all versions of a crate.
+    ///
+    /// Errors refer to the URL of the index file in place of a path.
+    pub async fn versions(
+        &self,
+        fetcher: &Fetcher,
+        name: &str,
+    ) -> Vec<Result<Version, IndexError>> {
+        let url = self.url(name);
+        match fetcher.fetch(&url).await {
+            Ok(contents) => parse_versions(Path::new(&url), &contents),
+            Err(error) => vec![Err(IndexError {
+                path: PathBuf::from(url),
+                line: None,
+                error,
+            })],
+        }
+    }
+}
+
+/// Read a list of crate names, one per line.
+///
+/// Blank lines and lines starting with `#` are ignored.
+pub fn read_crate_list<P: AsRef<Path>>(path: P) -> Result<Vec<String>, String> {
+    let path = path.as_ref();
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("cannot read crate list {}: {}", path.display(), e))?;
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_owned)
+        .collect())
+}
@@ -0,0 +1,61 @@
+//! Limiting the request rate across all workers.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The lowest supported rate, one request every 1000 seconds.
+pub const MIN_RATE: f64 = 0.001;
+
+/// A token bucket shared by all workers.
+///
+/// The bucket holds up to `burst` tokens and is refilled at a constant rate.
+/// Each request takes one token; if none is left, the caller reserves the next
+/// one and sleeps until it becomes available, so waiting workers are served in
+/// the order they arrived.
+pub struct RateLimiter {
+    per_second: f64,
+    burst: f64,
+    state: Mutex<Bucket>,
+}
+
+struct Bucket {
+    /// The number of available tokens; negative if tokens have been reserved.
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    /// Create a rate limiter allowing `per_second` requests per second on
+    /// average, and up to `burst` requests at once.
+    ///
+    /// The rate should be finite and at least `MIN_RATE`.
+    pub fn new(per_second: f64, burst: u32) -> Self {
+        let burst = f64::from(burst.max(1));
+        RateLimiter {
+            per_second,
+            burst,
+            state: Mutex::new(Bucket {
+                tokens: burst,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Wait until the caller may make the next request.
+    pub async fn acquire(&self) {
+        let wait = {
+            let mut bucket = self.state.lock().unwrap();
+            let now = Instant::now();
+            let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * self.per_second).min(self.burst);
+            bucket.last_refill = now;
+            bucket.tokens -= 1.0;
+            if bucket.tokens >= 0.0 {
+                return;
+            }
+            // Only reachable with rates far below the minimum.
+            Duration::try_from_secs_f64(-bucket.tokens / self.per_second).unwrap_or(Duration::MAX)
+        };
+        tokio::time::sleep(wait).await;
+    }
+}